@@ -0,0 +1,1117 @@
+use crate::conversion_functions::level_of_detail_to_number;
+use crate::error_functions::{Error, Result, write_error};
+use crate::feature_functions::{
+    CityObjectFeature, ImplicitTransformation, ParsedCityObject, ParsedCityObjects,
+    city_object_feature, feature_class, linear_ring, top_level_class,
+};
+use crate::geometry_functions::{Triangulator, triangulate};
+use ecitygml_core::model::common::{CityObjectClass, LevelOfDetail};
+use egml::model::base::{Gml, Id};
+use egml::model::geometry::{DirectPosition, MultiSurface, Polygon};
+use egml::operations::geometry::Geometry;
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Vertices the geometries of a CityJSON file (or of a CityJSONSeq line) refer to, together with
+// the geometry templates of the file
+struct GeometryContext<'a> {
+    vertices: &'a [[f64; 3]],
+    templates: &'a [Value],
+    template_vertices: &'a [[f64; 3]],
+}
+
+// Reads a CityJSON file (.json, .city.json) into the same structures as a CityGML file, so the
+// triangulation and the OBJ and metadata output work unchanged. Every semantic surface of a
+// geometry becomes a thematic surface (e.g. a WallSurface) of the city object.
+pub fn parse_cityjson(document: &str) -> Result<ParsedCityObjects> {
+    let cityjson: Value = serde_json::from_str(document)?;
+    if cityjson["type"] != "CityJSON" {
+        return Err(invalid_data("The file is not a CityJSON file"));
+    }
+
+    let vertices = read_vertices(&cityjson["vertices"], &cityjson["transform"]);
+    let templates = &cityjson["geometry-templates"];
+    let template_vertices = read_vertices(&templates["vertices-templates"], &Value::Null);
+    let context = GeometryContext {
+        vertices: &vertices,
+        templates: templates["templates"].as_array().map_or(&[], Vec::as_slice),
+        template_vertices: &template_vertices,
+    };
+
+    let mut city_objects = ParsedCityObjects::new();
+    read_city_objects(&cityjson["CityObjects"], &context, &mut city_objects);
+    Ok(city_objects)
+}
+
+// Reads a CityJSONSeq file (.jsonl): a CityJSON object with the transform and the geometry
+// templates on the first line, followed by one CityJSONFeature with its own vertices per line
+pub fn parse_cityjson_seq(document: &str) -> Result<ParsedCityObjects> {
+    let mut lines = document.lines().filter(|line| !line.trim().is_empty());
+    let header: Value = match lines.next() {
+        Some(line) => serde_json::from_str(line)?,
+        None => return Err(invalid_data("The CityJSONSeq file is empty")),
+    };
+    if header["type"] != "CityJSON" {
+        return Err(invalid_data(
+            "The first line of the CityJSONSeq file is not a CityJSON object",
+        ));
+    }
+
+    let templates = &header["geometry-templates"];
+    let template_vertices = read_vertices(&templates["vertices-templates"], &Value::Null);
+
+    let mut city_objects = ParsedCityObjects::new();
+    for (line_number, line) in lines.enumerate() {
+        let feature: Value = serde_json::from_str(line)?;
+        if feature["type"] != "CityJSONFeature" {
+            eprintln!(
+                "Line {} of the CityJSONSeq file is not a CityJSONFeature.",
+                line_number + 2
+            );
+            continue;
+        }
+
+        let vertices = read_vertices(&feature["vertices"], &header["transform"]);
+        let context = GeometryContext {
+            vertices: &vertices,
+            templates: templates["templates"].as_array().map_or(&[], Vec::as_slice),
+            template_vertices: &template_vertices,
+        };
+        read_city_objects(&feature["CityObjects"], &context, &mut city_objects);
+    }
+
+    Ok(city_objects)
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::CityJson(message.to_string())
+}
+
+// Applies the scale and translation of the "transform" member to the integer vertices
+fn read_vertices(vertices: &Value, transform: &Value) -> Vec<[f64; 3]> {
+    let vector = |value: &Value, default: f64| -> [f64; 3] {
+        let mut vector = [default; 3];
+        for (axis, component) in value.as_array().into_iter().flatten().take(3).enumerate() {
+            vector[axis] = component.as_f64().unwrap_or(default);
+        }
+        vector
+    };
+    let scale = vector(&transform["scale"], 1.0);
+    let translate = vector(&transform["translate"], 0.0);
+
+    vertices
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|vertex| {
+            let vertex = vector(vertex, f64::NAN);
+            [
+                vertex[0] * scale[0] + translate[0],
+                vertex[1] * scale[1] + translate[1],
+                vertex[2] * scale[2] + translate[2],
+            ]
+        })
+        .collect()
+}
+
+// Every city object without parents whose type can be converted becomes a top-level city
+// object; its children (building parts, installations, ...) are added as its members
+fn read_city_objects(
+    city_objects: &Value,
+    context: &GeometryContext,
+    parsed_city_objects: &mut ParsedCityObjects,
+) {
+    let Some(city_objects) = city_objects.as_object() else {
+        return;
+    };
+
+    for (id, city_object) in city_objects {
+        if city_object["parents"]
+            .as_array()
+            .is_some_and(|parents| !parents.is_empty())
+        {
+            continue;
+        }
+        let Some((class, class_name)) = city_object["type"].as_str().and_then(top_level_type)
+        else {
+            continue;
+        };
+        let Ok(gml_id) = Id::try_from(id.as_str()) else {
+            continue;
+        };
+
+        let mut members = Vec::new();
+        read_city_object(
+            id,
+            (class, class_name),
+            id.clone(),
+            city_object,
+            city_objects,
+            context,
+            &mut members,
+        );
+        parsed_city_objects.insert(
+            id.clone(),
+            ParsedCityObject {
+                id: gml_id,
+                class,
+                class_name,
+                members,
+            },
+        );
+    }
+}
+
+fn top_level_type(cityjson_type: &str) -> Option<(CityObjectClass, &'static str)> {
+    match cityjson_type {
+        "TransportSquare" => Some((CityObjectClass::Square, "TransportSquare")),
+        _ => top_level_class(cityjson_type.as_bytes()),
+    }
+}
+
+// Adds a city object, the thematic surfaces of its geometries and all of its children to
+// `members`
+fn read_city_object(
+    id: &str,
+    class: (CityObjectClass, &'static str),
+    parent_id: String,
+    city_object: &Value,
+    city_objects: &Map<String, Value>,
+    context: &GeometryContext,
+    members: &mut Vec<CityObjectFeature>,
+) {
+    let Ok(gml_id) = Id::try_from(id) else {
+        return;
+    };
+
+    // Several geometries may map onto the same level of detail (e.g. lod 2.0 and 2.2); only the
+    // most refined (or else the first) one is read, so they are not written on top of each other
+    let geometries: Vec<&Value> = city_object["geometry"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut most_refined: HashMap<LevelOfDetail, (f64, usize)> = HashMap::new();
+    for (geometry_index, geometry) in geometries.iter().enumerate() {
+        if let Some((lod, refinement)) = surface_geometry_lod(geometry, context.templates) {
+            most_refined
+                .entry(lod)
+                .and_modify(|selected| {
+                    if refinement > selected.0 {
+                        *selected = (refinement, geometry_index);
+                    }
+                })
+                .or_insert((refinement, geometry_index));
+        }
+    }
+
+    let mut polygons_by_lod: HashMap<LevelOfDetail, (Id, Vec<Polygon>)> = HashMap::new();
+    for (geometry_index, geometry) in geometries.into_iter().enumerate() {
+        let is_selected = |(lod, _): (LevelOfDetail, f64)| {
+            most_refined
+                .get(&lod)
+                .is_some_and(|(_, index)| *index == geometry_index)
+        };
+        if surface_geometry_lod(geometry, context.templates).is_some_and(|lod| !is_selected(lod)) {
+            continue;
+        }
+        let geometry_id = format!("{}_{}", id, geometry_index);
+        read_geometry(
+            id,
+            &geometry_id,
+            geometry,
+            context.vertices,
+            context,
+            &mut polygons_by_lod,
+            members,
+        );
+    }
+    members.push(city_object_feature(
+        gml_id,
+        class,
+        parent_id,
+        polygons_by_lod,
+    ));
+
+    for child_id in city_object["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        let Some(child) = city_objects.get(child_id) else {
+            eprintln!(
+                "City object {} refers to the missing child {}.",
+                id, child_id
+            );
+            continue;
+        };
+        // Children of other types (e.g. rooms or storeys) are skipped
+        let Some(child_class) = child["type"]
+            .as_str()
+            .and_then(|t| feature_class(t.as_bytes()))
+        else {
+            continue;
+        };
+        read_city_object(
+            child_id,
+            child_class,
+            id.to_string(),
+            child,
+            city_objects,
+            context,
+            members,
+        );
+    }
+}
+
+// Reads the polygons of a geometry. Polygons without a semantic surface belong to the city
+// object itself; the others are added as thematic surface members with the gml:id
+// <geometry id>_s<semantic surface index> (e.g. B1_0_s2 for the third semantic surface of the
+// first geometry of B1). Polygons are named <geometry id>_<surface index>.
+fn read_geometry(
+    city_object_id: &str,
+    geometry_id: &str,
+    geometry: &Value,
+    vertices: &[[f64; 3]],
+    context: &GeometryContext,
+    polygons_by_lod: &mut HashMap<LevelOfDetail, (Id, Vec<Polygon>)>,
+    members: &mut Vec<CityObjectFeature>,
+) {
+    let depth = match geometry["type"].as_str() {
+        Some("MultiSurface" | "CompositeSurface") => 1,
+        Some("Solid") => 2,
+        Some("MultiSolid" | "CompositeSolid") => 3,
+        Some("GeometryInstance") => {
+            read_geometry_instance(
+                city_object_id,
+                geometry_id,
+                geometry,
+                context,
+                polygons_by_lod,
+                members,
+            );
+            return;
+        }
+        // Points and lines are not converted
+        _ => return,
+    };
+    let Some((lod, _)) = cityjson_lod(&geometry["lod"]) else {
+        eprintln!(
+            "Geometry {} of city object {} has no valid lod.",
+            geometry_id, city_object_id
+        );
+        return;
+    };
+    let Ok(multi_surface_id) = Id::try_from(geometry_id) else {
+        return;
+    };
+
+    let mut surfaces = Vec::new();
+    collect_surfaces(
+        &geometry["boundaries"],
+        Some(&geometry["semantics"]["values"]),
+        depth,
+        &mut surfaces,
+    );
+    let semantic_surfaces = geometry["semantics"]["surfaces"]
+        .as_array()
+        .map_or(&[][..], Vec::as_slice);
+
+    let mut own_polygons = Vec::new();
+    let mut semantic_polygons: BTreeMap<usize, Vec<Polygon>> = BTreeMap::new();
+    for (surface_index, (surface, semantic_index)) in surfaces.into_iter().enumerate() {
+        let polygon_id = format!("{}_{}", geometry_id, surface_index);
+        let Some(polygon) = cityjson_polygon(&polygon_id, surface, vertices) else {
+            continue;
+        };
+        match semantic_index.filter(|index| *index < semantic_surfaces.len()) {
+            Some(index) => semantic_polygons.entry(index).or_default().push(polygon),
+            None => own_polygons.push(polygon),
+        }
+    }
+
+    polygons_by_lod
+        .entry(lod)
+        .or_insert_with(|| (multi_surface_id.clone(), Vec::new()))
+        .1
+        .extend(own_polygons);
+
+    let semantic_surface_id = |index: usize| format!("{}_s{}", geometry_id, index);
+    for (index, polygons) in semantic_polygons {
+        let semantic_surface = &semantic_surfaces[index];
+        let Ok(surface_id) = Id::try_from(semantic_surface_id(index)) else {
+            continue;
+        };
+        // A semantic surface may belong to another one, e.g. a Window to a WallSurface
+        let parent_id = semantic_surface["parent"]
+            .as_u64()
+            .map(|parent| semantic_surface_id(parent as usize))
+            .unwrap_or(city_object_id.to_string());
+        let class = semantic_surface["type"]
+            .as_str()
+            .map(semantic_surface_class)
+            .unwrap_or((
+                CityObjectClass::GenericThematicSurface,
+                "GenericThematicSurface",
+            ));
+
+        members.push(city_object_feature(
+            surface_id,
+            class,
+            parent_id,
+            HashMap::from([(lod, (multi_surface_id.clone(), polygons))]),
+        ));
+    }
+}
+
+// Places the template of a GeometryInstance with its transformation matrix at the vertex it
+// refers to and reads it like any other geometry
+fn read_geometry_instance(
+    city_object_id: &str,
+    geometry_id: &str,
+    geometry: &Value,
+    context: &GeometryContext,
+    polygons_by_lod: &mut HashMap<LevelOfDetail, (Id, Vec<Polygon>)>,
+    members: &mut Vec<CityObjectFeature>,
+) {
+    let template = geometry["template"]
+        .as_u64()
+        .and_then(|index| context.templates.get(index as usize));
+    let reference_point = geometry["boundaries"][0]
+        .as_u64()
+        .and_then(|index| context.vertices.get(index as usize));
+    let (Some(template), Some(reference_point)) = (template, reference_point) else {
+        eprintln!(
+            "GeometryInstance {} of city object {} refers to a missing template or vertex.",
+            geometry_id, city_object_id
+        );
+        return;
+    };
+
+    let mut matrix = [
+        1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+    ];
+    if let Some(values) = geometry["transformationMatrix"].as_array()
+        && values.len() == 16
+    {
+        for (element, value) in matrix.iter_mut().zip(values) {
+            *element = value.as_f64().unwrap_or(*element);
+        }
+    }
+    let transformation = ImplicitTransformation {
+        matrix,
+        reference_point: *reference_point,
+    };
+    let instance_vertices: Vec<[f64; 3]> = context
+        .template_vertices
+        .iter()
+        .map(|vertex| transformation.transform_point(*vertex))
+        .collect();
+
+    read_geometry(
+        city_object_id,
+        geometry_id,
+        template,
+        &instance_vertices,
+        context,
+        polygons_by_lod,
+        members,
+    );
+}
+
+// Collects the surfaces of the boundaries (nested `depth` levels deep) together with their
+// semantic surface index, which is nested the same way in the semantic values
+fn collect_surfaces<'a>(
+    boundaries: &'a Value,
+    values: Option<&'a Value>,
+    depth: usize,
+    surfaces: &mut Vec<(&'a Value, Option<usize>)>,
+) {
+    if depth == 0 {
+        let value = values.and_then(Value::as_u64).map(|value| value as usize);
+        surfaces.push((boundaries, value));
+        return;
+    }
+    for (index, boundary) in boundaries.as_array().into_iter().flatten().enumerate() {
+        collect_surfaces(
+            boundary,
+            values.and_then(|values| values.get(index)),
+            depth - 1,
+            surfaces,
+        );
+    }
+}
+
+// Builds a polygon from a surface given as an array of rings of vertex indices, the first ring
+// being the exterior one
+fn cityjson_polygon(polygon_id: &str, surface: &Value, vertices: &[[f64; 3]]) -> Option<Polygon> {
+    let id = Id::try_from(polygon_id).ok()?;
+    let mut rings = Vec::new();
+    for (ring_index, ring) in surface.as_array().into_iter().flatten().enumerate() {
+        let points: Option<Vec<DirectPosition>> = ring
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|index| {
+                let [x, y, z] = *vertices.get(index.as_u64()? as usize)?;
+                DirectPosition::new(x, y, z).ok()
+            })
+            .collect();
+        let Some(points) = points else {
+            eprintln!("Polygon {} refers to invalid vertices.", polygon_id);
+            return None;
+        };
+
+        let ring_id = Id::from_hashed_string(&format!("{}_{}", polygon_id, ring_index));
+        match linear_ring(ring_id, points) {
+            Ok(ring) => rings.push(ring),
+            Err(e) if ring_index == 0 => {
+                eprintln!("Polygon {} contains an invalid ring: {}", polygon_id, e);
+                return None;
+            }
+            Err(e) => eprintln!("Polygon {} contains an invalid ring: {}", polygon_id, e),
+        }
+    }
+
+    if rings.is_empty() {
+        return None;
+    }
+    let exterior = rings.remove(0);
+    Polygon::new(Gml::new(id), exterior, rings).ok()
+}
+
+// The lod of a geometry is given as a string (e.g. "2.2") or as a number in older versions.
+// Returns the level of detail and the lod itself, which tells the refinements of a level apart.
+fn cityjson_lod(lod: &Value) -> Option<(LevelOfDetail, f64)> {
+    let lod = match lod {
+        Value::String(lod) => lod.parse::<f64>().ok()?,
+        Value::Number(lod) => lod.as_f64()?,
+        _ => return None,
+    };
+    let level = match lod.floor() as i64 {
+        0 => LevelOfDetail::Zero,
+        1 => LevelOfDetail::One,
+        2 => LevelOfDetail::Two,
+        3 | 4 => LevelOfDetail::Three,
+        _ => return None,
+    };
+    Some((level, lod))
+}
+
+// The lod of a geometry with surfaces; geometry instances have the one of their template
+fn surface_geometry_lod(geometry: &Value, templates: &[Value]) -> Option<(LevelOfDetail, f64)> {
+    match geometry["type"].as_str()? {
+        "MultiSurface" | "CompositeSurface" | "Solid" | "MultiSolid" | "CompositeSolid" => {
+            cityjson_lod(&geometry["lod"])
+        }
+        "GeometryInstance" => {
+            let template = templates.get(geometry["template"].as_u64()? as usize)?;
+            surface_geometry_lod(template, &[])
+        }
+        _ => None,
+    }
+}
+
+fn semantic_surface_class(surface_type: &str) -> (CityObjectClass, &'static str) {
+    match surface_type {
+        "TransportationMarking" => (CityObjectClass::Marking, "Marking"),
+        "TransportationHole" => (CityObjectClass::Hole, "Hole"),
+        "InteriorWallSurface" => (
+            CityObjectClass::GenericThematicSurface,
+            "InteriorWallSurface",
+        ),
+        "CeilingSurface" => (CityObjectClass::GenericThematicSurface, "CeilingSurface"),
+        "FloorSurface" => (CityObjectClass::GenericThematicSurface, "FloorSurface"),
+        _ => feature_class(surface_type.as_bytes()).unwrap_or((
+            CityObjectClass::GenericThematicSurface,
+            "GenericThematicSurface",
+        )),
+    }
+}
+
+// Geometry written into CityJSON files: the triangles of the triangulation or the original
+// polygons with their holes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CityJsonGeometry {
+    Triangles,
+    Polygons,
+}
+
+// Vertices are written as integers in millimetres relative to the translation
+const CITYJSON_SCALE: f64 = 0.001;
+
+// A feature of a top-level city object with the geometry to write
+#[derive(Debug)]
+pub struct CityJsonFeature<'a> {
+    pub id: String,
+    pub class_name: &'a str,
+    pub parent_id: &'a str,
+    pub multi_surfaces: Vec<(LevelOfDetail, &'a MultiSurface)>,
+}
+
+// Integer vertices of the file relative to the centre of the city object, every position is
+// only written once
+struct CityJsonVertices {
+    center: [f64; 3],
+    vertices: Vec<[i64; 3]>,
+    indices: HashMap<[i64; 3], usize>,
+}
+
+impl CityJsonVertices {
+    fn index(&mut self, point: &[f64; 3]) -> usize {
+        let vertex = [0, 1, 2]
+            .map(|axis| ((point[axis] - self.center[axis]) / CITYJSON_SCALE).round() as i64);
+        *self.indices.entry(vertex).or_insert_with(|| {
+            self.vertices.push(vertex);
+            self.vertices.len() - 1
+        })
+    }
+}
+
+// The MultiSurface of a city object in one level of detail, with its semantic surfaces
+#[derive(Default)]
+struct CityJsonGeometryBuilder<'a> {
+    boundaries: Vec<Value>,
+    values: Vec<Option<usize>>,
+    // gml:id, semantic surface type and parent feature of every semantic surface
+    surfaces: Vec<(&'a str, &'static str, &'a str)>,
+}
+
+impl<'a> CityJsonGeometryBuilder<'a> {
+    fn semantic_index(
+        &mut self,
+        feature: &'a CityJsonFeature,
+        surface_type: Option<&'static str>,
+    ) -> Option<usize> {
+        let surface_type = surface_type?;
+        if let Some(index) = self
+            .surfaces
+            .iter()
+            .position(|(id, _, _)| *id == feature.id)
+        {
+            return Some(index);
+        }
+        self.surfaces
+            .push((&feature.id, surface_type, feature.parent_id));
+        Some(self.surfaces.len() - 1)
+    }
+
+    fn to_json(&self, lod: LevelOfDetail) -> Value {
+        let mut geometry = json!({
+            "type": "MultiSurface",
+            "lod": level_of_detail_to_number(lod).to_string(),
+            "boundaries": self.boundaries,
+        });
+        if !self.surfaces.is_empty() {
+            // Semantic surfaces keep their gml:id and their parent surface (e.g. the
+            // WallSurface of a Window)
+            let surfaces: Vec<Value> = self
+                .surfaces
+                .iter()
+                .map(|(id, surface_type, parent_id)| {
+                    let mut surface = json!({ "type": surface_type, "gml_id": id });
+                    if let Some(parent) = self.surfaces.iter().position(|(s, _, _)| s == parent_id)
+                    {
+                        surface["parent"] = json!(parent);
+                    }
+                    let children: Vec<usize> = self
+                        .surfaces
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, _, parent))| parent == id)
+                        .map(|(index, _)| index)
+                        .collect();
+                    if !children.is_empty() {
+                        surface["children"] = json!(children);
+                    }
+                    surface
+                })
+                .collect();
+            geometry["semantics"] = json!({ "surfaces": surfaces, "values": self.values });
+        }
+        geometry
+    }
+}
+
+// The city objects written for a top-level city object, merged into the CityJSON file of the
+// input document by `write_cityjson_file`
+#[derive(Debug, Clone)]
+pub struct CityJsonCityObjects {
+    pub city_object_id: String,
+    city_objects: Map<String, Value>,
+    // Integer vertices relative to `origin`, the translation in millimetres
+    vertices: Vec<[i64; 3]>,
+    origin: [i64; 3],
+}
+
+// Converts a top-level city object and its features into CityJSON 2.0 city objects. The gml:ids
+// are the keys of the city objects, building parts, installations etc. become child city
+// objects and thematic surfaces become semantic surfaces of the geometry of the city object
+// they belong to. Features without a CityJSON counterpart (e.g. traffic spaces) are added to
+// the geometry of their closest ancestor without semantics. `center`, given in the coordinates
+// of the features, lies at `translate` in the CRS.
+pub fn cityjson_city_objects(
+    city_object_id: &str,
+    city_object_class: &str,
+    features: &[CityJsonFeature],
+    center: [f64; 3],
+    translate: [f64; 3],
+    geometry_type: CityJsonGeometry,
+    triangulator: Triangulator,
+) -> Result<CityJsonCityObjects> {
+    // The translation is rounded to millimetres, so the vertices of all city objects of a file
+    // can be moved onto a common translation without rounding them again
+    let origin = translate.map(|c| (c / CITYJSON_SCALE).round() as i64);
+    let center =
+        [0, 1, 2].map(|axis| center[axis] + origin[axis] as f64 * CITYJSON_SCALE - translate[axis]);
+
+    let mut features: Vec<&CityJsonFeature> = features.iter().collect();
+    features.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let classes: HashMap<&str, &str> = features
+        .iter()
+        .map(|f| (f.id.as_str(), f.class_name))
+        .collect();
+    let parents: HashMap<&str, &str> = features
+        .iter()
+        .map(|f| (f.id.as_str(), f.parent_id))
+        .collect();
+    let is_city_object = |id: &str| {
+        id == city_object_id
+            || classes
+                .get(id)
+                .is_some_and(|class| cityjson_object_type(class).is_some())
+    };
+    // The city object the geometry of a feature is written to: the feature itself or its
+    // closest ancestor that is a city object
+    let owner = |id: &str| -> String {
+        let mut current = id;
+        for _ in 0..=features.len() {
+            if is_city_object(current) {
+                return current.to_string();
+            }
+            match parents.get(current) {
+                Some(parent) if *parent != current => current = parent,
+                _ => break,
+            }
+        }
+        city_object_id.to_string()
+    };
+
+    let mut vertices = CityJsonVertices {
+        center,
+        vertices: Vec::new(),
+        indices: HashMap::new(),
+    };
+    let mut geometries: BTreeMap<(String, u8), (LevelOfDetail, CityJsonGeometryBuilder)> =
+        BTreeMap::new();
+
+    for feature in &features {
+        let owner_id = owner(&feature.id);
+        let surface_type = if is_city_object(&feature.id) {
+            None
+        } else {
+            cityjson_semantic_type(feature.class_name)
+        };
+
+        for (lod, multi_surface) in &feature.multi_surfaces {
+            let (_, builder) = geometries
+                .entry((owner_id.clone(), level_of_detail_to_number(*lod)))
+                .or_insert_with(|| (*lod, CityJsonGeometryBuilder::default()));
+            let semantic_index = builder.semantic_index(feature, surface_type);
+
+            for polygon in multi_surface.surface_member() {
+                let surfaces = match geometry_type {
+                    CityJsonGeometry::Triangles => {
+                        triangle_boundaries(polygon, triangulator, &mut vertices)
+                    }
+                    CityJsonGeometry::Polygons => polygon_boundaries(polygon, &mut vertices)
+                        .into_iter()
+                        .collect(),
+                };
+                for surface in surfaces {
+                    builder.boundaries.push(surface);
+                    builder.values.push(semantic_index);
+                }
+            }
+        }
+    }
+
+    // City objects with their geometries, parents and children
+    let mut city_objects = Map::new();
+    let object_ids = std::iter::once(city_object_id.to_string()).chain(
+        features
+            .iter()
+            .filter(|f| f.id != city_object_id && is_city_object(&f.id))
+            .map(|f| f.id.clone()),
+    );
+    for object_id in object_ids {
+        let object_type = match object_id == city_object_id {
+            true => cityjson_object_type(city_object_class).unwrap_or("GenericCityObject"),
+            false => {
+                cityjson_object_type(classes[object_id.as_str()]).unwrap_or("GenericCityObject")
+            }
+        };
+        let object_geometries: Vec<Value> = geometries
+            .iter()
+            .filter(|((owner_id, _), _)| *owner_id == object_id)
+            .map(|(_, (lod, builder))| builder.to_json(*lod))
+            .collect();
+        let mut city_object = json!({ "type": object_type, "geometry": object_geometries });
+
+        if object_id != city_object_id
+            && let Some(parent_id) = parents.get(object_id.as_str())
+        {
+            city_object["parents"] = json!([owner(parent_id)]);
+        }
+        let children: Vec<&str> = features
+            .iter()
+            .filter(|f| f.id != object_id && f.id != city_object_id && is_city_object(&f.id))
+            .filter(|f| owner(f.parent_id) == object_id)
+            .map(|f| f.id.as_str())
+            .collect();
+        if !children.is_empty() {
+            city_object["children"] = json!(children);
+        }
+        city_objects.insert(object_id, city_object);
+    }
+
+    if vertices.vertices.is_empty() {
+        return Err(Error::NoGeometry(city_object_id.to_string()));
+    }
+
+    Ok(CityJsonCityObjects {
+        city_object_id: city_object_id.to_string(),
+        city_objects,
+        vertices: vertices.vertices,
+        origin,
+    })
+}
+
+// Writes the city objects of an input document as CityJSON 2.0 (<file_stem>.city.json). The
+// vertices are stored in millimetres relative to the centre of the translations of the city
+// objects, which is written as transform.translate.
+pub fn write_cityjson_file(
+    file_stem: &str,
+    city_objects: &[CityJsonCityObjects],
+    epsg: Option<u32>,
+    output_dir: &Path,
+) -> Result<()> {
+    if city_objects.is_empty() {
+        return Err(Error::NoGeometry(file_stem.to_string()));
+    }
+    let origins = || city_objects.iter().map(|c| c.origin);
+    let origin = [0, 1, 2].map(|axis| {
+        let lower = origins().map(|o| o[axis]).min().unwrap_or_default();
+        let upper = origins().map(|o| o[axis]).max().unwrap_or_default();
+        lower + (upper - lower) / 2
+    });
+
+    let mut merged_city_objects = Map::new();
+    let mut vertices: Vec<[i64; 3]> = Vec::new();
+    for city_object in city_objects {
+        let offset = [0, 1, 2].map(|axis| city_object.origin[axis] - origin[axis]);
+        let index_offset = vertices.len() as u64;
+        vertices.extend(
+            city_object
+                .vertices
+                .iter()
+                .map(|vertex| [0, 1, 2].map(|axis| vertex[axis] + offset[axis])),
+        );
+        for (id, object) in &city_object.city_objects {
+            let mut object = object.clone();
+            for geometry in object["geometry"].as_array_mut().into_iter().flatten() {
+                offset_boundaries(&mut geometry["boundaries"], index_offset);
+            }
+            merged_city_objects.insert(id.clone(), object);
+        }
+    }
+
+    let mut document = json!({
+        "type": "CityJSON",
+        "version": "2.0",
+        "transform": {
+            "scale": [CITYJSON_SCALE, CITYJSON_SCALE, CITYJSON_SCALE],
+            "translate": origin.map(|c| c as f64 * CITYJSON_SCALE),
+        },
+        "CityObjects": merged_city_objects,
+        "vertices": vertices,
+    });
+    if let Some(epsg) = epsg {
+        document["metadata"] = json!({
+            "referenceSystem": format!("https://www.opengis.net/def/crs/EPSG/0/{}", epsg),
+        });
+    }
+
+    let file_path = output_dir.join(format!("{}.city.json", file_stem));
+    let file = File::create(&file_path).map_err(write_error(&file_path))?;
+    serde_json::to_writer(BufWriter::new(file), &document)
+        .map_err(|e| write_error(&file_path)(e.into()))
+}
+
+// Moves the vertex indices of nested boundaries by `offset`
+fn offset_boundaries(boundaries: &mut Value, offset: u64) {
+    match boundaries {
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| offset_boundaries(value, offset)),
+        Value::Number(index) => {
+            if let Some(index) = index.as_u64() {
+                *boundaries = json!(index + offset);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Every triangle becomes a surface of its own; triangles that collapse when the vertices are
+// rounded to millimetres are dropped
+fn triangle_boundaries(
+    polygon: &Polygon,
+    triangulator: Triangulator,
+    vertices: &mut CityJsonVertices,
+) -> Vec<Value> {
+    let (triangles, points) = triangulate(polygon, triangulator);
+    let indices: Vec<usize> = points.iter().map(|p| vertices.index(p)).collect();
+
+    triangles
+        .chunks_exact(3)
+        .map(|t| [0, 1, 2].map(|i| indices[t[i] as usize]))
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .map(|triangle| json!([triangle]))
+        .collect()
+}
+
+fn polygon_boundaries(polygon: &Polygon, vertices: &mut CityJsonVertices) -> Option<Value> {
+    let mut rings = Vec::new();
+    for (ring_index, ring) in std::iter::once(&polygon.exterior)
+        .chain(&polygon.interior)
+        .enumerate()
+    {
+        let mut indices: Vec<usize> = ring
+            .points()
+            .into_iter()
+            .map(|p| vertices.index(&p.coords()))
+            .collect();
+        indices.dedup();
+        if indices.len() > 1 && indices.first() == indices.last() {
+            indices.pop();
+        }
+        if indices.len() >= 3 {
+            rings.push(indices);
+        } else if ring_index == 0 {
+            return None;
+        }
+    }
+    Some(json!(rings))
+}
+
+fn cityjson_object_type(class_name: &str) -> Option<&'static str> {
+    let object_type = match class_name {
+        "Building" => "Building",
+        "BuildingPart" => "BuildingPart",
+        "BuildingInstallation" => "BuildingInstallation",
+        "BuildingConstructiveElement" => "BuildingConstructiveElement",
+        "Bridge" => "Bridge",
+        "BridgePart" => "BridgePart",
+        "BridgeInstallation" => "BridgeInstallation",
+        "BridgeConstructiveElement" => "BridgeConstructiveElement",
+        "Tunnel" => "Tunnel",
+        "TunnelPart" => "TunnelPart",
+        "TunnelInstallation" => "TunnelInstallation",
+        "TunnelConstructiveElement" => "TunnelConstructiveElement",
+        "CityFurniture" => "CityFurniture",
+        "Road" | "Track" | "TransportationComplex" => "Road",
+        "Railway" => "Railway",
+        "Waterway" => "Waterway",
+        "Square" | "TransportSquare" => "TransportSquare",
+        "WaterBody" => "WaterBody",
+        "PlantCover" => "PlantCover",
+        "SolitaryVegetationObject" => "SolitaryVegetationObject",
+        "OtherConstruction" => "OtherConstruction",
+        "GenericOccupiedSpace" | "GenericUnoccupiedSpace" | "GenericCityObject" => {
+            "GenericCityObject"
+        }
+        _ => return None,
+    };
+    Some(object_type)
+}
+
+fn cityjson_semantic_type(class_name: &str) -> Option<&'static str> {
+    let surface_type = match class_name {
+        "WallSurface" => "WallSurface",
+        "RoofSurface" => "RoofSurface",
+        "GroundSurface" => "GroundSurface",
+        "ClosureSurface" => "ClosureSurface",
+        "OuterCeilingSurface" => "OuterCeilingSurface",
+        "OuterFloorSurface" => "OuterFloorSurface",
+        "Window" | "WindowSurface" => "Window",
+        "Door" | "DoorSurface" => "Door",
+        "InteriorWallSurface" => "InteriorWallSurface",
+        "CeilingSurface" => "CeilingSurface",
+        "FloorSurface" => "FloorSurface",
+        "WaterSurface" => "WaterSurface",
+        "WaterGroundSurface" => "WaterGroundSurface",
+        "WaterClosureSurface" => "WaterClosureSurface",
+        "TrafficArea" => "TrafficArea",
+        "AuxiliaryTrafficArea" => "AuxiliaryTrafficArea",
+        "Marking" | "TransportationMarking" => "TransportationMarking",
+        "Hole" | "HoleSurface" | "TransportationHole" => "TransportationHole",
+        _ => return None,
+    };
+    Some(surface_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_most_refined_geometry_of_a_level_of_detail_is_read() {
+        let document = r#"{
+            "type": "CityJSON",
+            "version": "2.0",
+            "transform": { "scale": [1.0, 1.0, 1.0], "translate": [0.0, 0.0, 0.0] },
+            "CityObjects": {
+                "B1": {
+                    "type": "Building",
+                    "geometry": [
+                        { "type": "MultiSurface", "lod": "2.0", "boundaries": [[[0, 1, 2, 3]]] },
+                        {
+                            "type": "MultiSurface",
+                            "lod": "2.2",
+                            "boundaries": [[[0, 1, 2, 3]], [[4, 5, 6, 7]]]
+                        },
+                        { "type": "MultiSurface", "lod": "1.2", "boundaries": [[[0, 1, 2, 3]]] }
+                    ]
+                }
+            },
+            "vertices": [
+                [0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0],
+                [0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]
+            ]
+        }"#;
+
+        let city_objects = parse_cityjson(document).unwrap();
+
+        let geometry = &city_objects["B1"].members[0].geometry;
+        let lod2 = &geometry.multi_surfaces[&LevelOfDetail::Two];
+        assert_eq!(lod2.gml.id.to_string(), "B1_1");
+        assert_eq!(lod2.surface_member().len(), 2);
+        assert!(geometry.multi_surfaces.contains_key(&LevelOfDetail::One));
+    }
+
+    // gml:id, class and exterior rings (in millimetres) of a feature with polygons
+    type Surface = (String, &'static str, Vec<Vec<[i64; 3]>>);
+
+    // All features with polygons, in a fixed order
+    fn surfaces(city_objects: &ParsedCityObjects) -> Vec<Surface> {
+        let mut surfaces: Vec<_> = city_objects
+            .values()
+            .flat_map(|city_object| &city_object.members)
+            .filter(|member| !member.geometry.multi_surfaces.is_empty())
+            .map(|member| {
+                let rings = member
+                    .geometry
+                    .multi_surfaces
+                    .values()
+                    .flat_map(|multi_surface| multi_surface.surface_member())
+                    .map(|polygon| {
+                        polygon
+                            .exterior
+                            .points()
+                            .iter()
+                            .map(|p| p.coords().map(|c| (c * 1000.0).round() as i64))
+                            .collect()
+                    })
+                    .collect();
+                (member.geometry.gml.id.to_string(), member.class_name, rings)
+            })
+            .collect();
+        surfaces.sort();
+        surfaces
+    }
+
+    #[test]
+    fn cityjson_round_trip_keeps_the_semantics_and_the_location() {
+        let document = r#"{
+            "type": "CityJSON",
+            "version": "2.0",
+            "transform": { "scale": [0.001, 0.001, 0.001], "translate": [690000.0, 5336000.0, 500.0] },
+            "CityObjects": {
+                "B1": {
+                    "type": "Building",
+                    "geometry": [{
+                        "type": "MultiSurface",
+                        "lod": "2",
+                        "boundaries": [[[4, 5, 6, 7]], [[0, 1, 5, 4]], [[1, 2, 6, 5]]],
+                        "semantics": {
+                            "surfaces": [{ "type": "RoofSurface" }, { "type": "WallSurface" }],
+                            "values": [0, 1, 1]
+                        }
+                    }]
+                }
+            },
+            "vertices": [
+                [0, 0, 0], [10000, 0, 0], [10000, 10000, 0], [0, 10000, 0],
+                [0, 0, 6000], [10000, 0, 6000], [10000, 10000, 6000], [0, 10000, 6000]
+            ]
+        }"#;
+        let city_objects = parse_cityjson(document).unwrap();
+        let building = &city_objects["B1"];
+        let features: Vec<CityJsonFeature> = building
+            .members
+            .iter()
+            .map(|member| CityJsonFeature {
+                id: member.geometry.gml.id.to_string(),
+                class_name: member.class_name,
+                parent_id: &member.parent_id,
+                multi_surfaces: member
+                    .geometry
+                    .multi_surfaces
+                    .iter()
+                    .map(|(lod, multi_surface)| (*lod, multi_surface))
+                    .collect(),
+            })
+            .collect();
+        let center = [690005.0, 5336005.0, 503.0];
+        let converted = cityjson_city_objects(
+            "B1",
+            building.class_name,
+            &features,
+            center,
+            center,
+            CityJsonGeometry::Polygons,
+            Triangulator::Earcut,
+        )
+        .unwrap();
+
+        let output = std::env::temp_dir()
+            .join("rust_citygml2obj_unit_tests")
+            .join("cityjson_round_trip");
+        std::fs::create_dir_all(&output).unwrap();
+        write_cityjson_file("round_trip", &[converted], Some(25832), &output).unwrap();
+        let written = std::fs::read_to_string(output.join("round_trip.city.json")).unwrap();
+
+        let written_json: Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(written_json["transform"]["translate"], json!(center));
+        let semantics = &written_json["CityObjects"]["B1"]["geometry"][0]["semantics"];
+        assert_eq!(semantics["surfaces"][0]["type"], "RoofSurface");
+        assert_eq!(semantics["surfaces"][1]["type"], "WallSurface");
+        assert_eq!(semantics["values"], json!([0, 1, 1]));
+        let read_again = parse_cityjson(&written).unwrap();
+        let expected = surfaces(&city_objects);
+        assert_eq!(
+            expected
+                .iter()
+                .map(|(_, class, _)| *class)
+                .collect::<Vec<_>>(),
+            ["RoofSurface", "WallSurface"]
+        );
+        assert_eq!(surfaces(&read_again), expected);
+    }
+}
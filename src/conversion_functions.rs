@@ -1,266 +1,1217 @@
-use crate::geometry_functions::{construct_buffered_bounding_box, triangulate};
-use crate::write_functions;
-use crate::write_functions::write_obj_file;
-use ecitygml::operations::GeometryCollector;
+use crate::ConversionOptions;
+use crate::cityjson_functions::{
+    CityJsonCityObjects, CityJsonFeature, CityJsonGeometry, cityjson_city_objects,
+};
+use crate::crs_functions::CrsTransformation;
+use crate::error_functions::{ConversionSummary, Error, Result};
+use crate::feature_functions::{CityObjectFeature, ParsedCityObject};
+use crate::geometry_functions::{
+    BoundingBoxGeometry, LocalOrigin, Normals, RayTarget, Triangulator, collected_envelope,
+    construct_buffered_bounding_box, envelope_center, faces_inward, import_bounding_box,
+    polygon_normal, reverse_polygon, transform_city_objects, triangulate, unit_normal,
+    vertex_centroid,
+};
+use crate::gltf_functions::{GltfNode, GltfPrimitive, gltf_node};
+use crate::write_functions::{
+    BuildingMetadata, CityObjectManifest, FileMetadata, GeometryCounts, ObjGroup,
+    SemanticSurfaceId, lod_suffix, material_name, write_grouped_obj_file, write_json_metadata,
+    write_obj_file,
+};
+use ecitygml::operations::{CityObjectGeometryCollection, GeometryCollector};
 use ecitygml_core::model::building::Building;
 use ecitygml_core::model::common::{CityObjectClass, LevelOfDetail};
-use ecitygml_core::operations::{FeatureWithGeometry, Visitable};
+use ecitygml_core::operations::Visitable;
 use egml::model::base::Id;
 use egml::model::geometry::{MultiSurface, Polygon};
-use egml::operations::triangulate::Triangulate;
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 
 // Helper container that stores all vertices and triangles that belong to one
 // semantic surface class (e.g. WallSurface, RoofSurface, …).
 #[derive(Debug, Default)]
-struct SurfaceGroup {
+pub struct SurfaceGroup {
     vertices: Vec<[f64; 3]>,
     triangles: Vec<u32>,
+    // Area-weighted normals of the vertices and the index of the thematic surface (in
+    // thematic_surface_ids) they belong to, if normals are written
+    normals: Vec<[f64; 3]>,
+    normal_surfaces: Vec<usize>,
     class_name: Option<String>,
+    // gml:ids of all members that contributed to this group, used for the metadata
+    parent_ids: Vec<String>,
+    thematic_surface_ids: Vec<String>,
+    multi_surface_ids: Vec<String>,
+    polygon_ids: Vec<String>,
 }
 
-pub fn collect_building_geometries(
-    input_building: &mut Building,
-    tbw: bool,
-    add_bb: bool,
-    add_json: bool,
-    import_bb: bool,
-    group_by_surface: bool,
-    group_by_semantic_surface: bool,
-) {
-    // Initialize an empty bounding box
-    let mut bbox = (Vec::new(), Vec::new());
+impl SurfaceGroup {
+    #[allow(clippy::too_many_arguments)]
+    fn add_polygon(
+        &mut self,
+        triangles: &[u32],
+        all_points: &[[f64; 3]],
+        normals: &[[f64; 3]],
+        parent_id: &str,
+        thematic_surface_id: &Id,
+        multi_surface_id: &Id,
+        polygon_id: &Id,
+    ) {
+        let vertex_offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(all_points);
+        self.triangles
+            .extend(triangles.iter().map(|idx| idx + vertex_offset));
 
-    // Distinguish the different cases of the bounding box
-    if add_bb {
-        bbox = construct_buffered_bounding_box(input_building);
-    } else if import_bb {
-        // Import the bounding box from an external file
-        // todo: Muss noch implementiert werden.
+        push_unique(&mut self.parent_ids, parent_id);
+        push_unique(&mut self.thematic_surface_ids, thematic_surface_id);
+        push_unique(&mut self.multi_surface_ids, multi_surface_id);
+        self.polygon_ids.push(polygon_id.to_string());
+
+        if !normals.is_empty() {
+            let surface = self
+                .thematic_surface_ids
+                .iter()
+                .position(|id| *id == thematic_surface_id.to_string())
+                .unwrap_or_default();
+            self.normals.extend_from_slice(normals);
+            self.normal_surfaces
+                .extend(std::iter::repeat_n(surface, normals.len()));
+        }
     }
 
-    let building_id = &input_building.occupied_space.space.city_object.gml.id;
+    // Unit normals of the vertices. Smooth normals are added up over all vertices of the same
+    // thematic surface at the same position, so the edges between surfaces stay sharp.
+    fn vertex_normals(&self, normals: Normals) -> Vec<[f64; 3]> {
+        if normals == Normals::Flat {
+            return self.normals.iter().map(|n| unit_normal(*n)).collect();
+        }
 
-    // get the translation parameter into a local crs in case it is desired
-    let mut dx: f64 = 0.0;
-    let mut dy: f64 = 0.0;
-    let mut dz: f64 = 0.0;
-    if tbw {
-        if let Some(envelope) = input_building.envelope() {
-            let upper_corner = envelope.upper_corner();
-            let lower_corner = envelope.lower_corner();
-            dx = -((upper_corner.x() + lower_corner.x()) / 2.0);
-            dy = -((upper_corner.y() + lower_corner.y()) / 2.0);
-            dz = -((upper_corner.z() + lower_corner.z()) / 2.0);
-        } else {
-            println!("Envelope hat keine gültigen lower/upper corner Koordinaten.");
+        let key = |i: usize| {
+            let v = self.vertices[i];
+            (
+                self.normal_surfaces[i],
+                [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()],
+            )
+        };
+        let mut sums: HashMap<(usize, [u64; 3]), [f64; 3]> = HashMap::new();
+        for (i, normal) in self.normals.iter().enumerate() {
+            let sum = sums.entry(key(i)).or_default();
+            for axis in 0..3 {
+                sum[axis] += normal[axis];
+            }
+        }
+        (0..self.normals.len())
+            .map(|i| unit_normal(sums[&key(i)]))
+            .collect()
+    }
+
+    fn file_metadata(&self, file_name: String, class_name: &str) -> FileMetadata {
+        FileMetadata {
+            file_name,
+            class: class_name.to_string(),
+            parent_gml_ids: self.parent_ids.clone(),
+            thematic_surface_gml_ids: self.thematic_surface_ids.clone(),
+            multi_surface_gml_ids: self.multi_surface_ids.clone(),
+            polygon_gml_ids: self.polygon_ids.clone(),
         }
     }
+}
+
+fn push_unique(ids: &mut Vec<String>, id: &(impl ToString + ?Sized)) {
+    let id = id.to_string();
+    if !ids.contains(&id) {
+        ids.push(id);
+    }
+}
+
+type SharedGroups = Mutex<HashMap<String, SurfaceGroup>>;
+
+// Accumulators shared by the parallel conversion of the polygons of a city object
+#[derive(Debug, Default)]
+pub(crate) struct SharedOutput {
+    // Polygons that were already triangulated before, e.g. to validate their orientation
+    triangulations: Triangulations,
+    groups_by_class: Option<SharedGroups>,
+    groups_by_semantic_surface: Option<SharedGroups>,
+    // Records of all written files for the city-object-level metadata and the manifest
+    file_metadata: Mutex<Vec<FileMetadata>>,
+    counts: Mutex<BTreeMap<String, GeometryCounts>>,
+    warnings: Mutex<Vec<String>>,
+    errors: Mutex<Vec<Error>>,
+    polygons_failed: AtomicUsize,
+}
+
+// Triangles and points of a polygon as returned by triangulate
+pub(crate) type Triangulation = (Vec<u32>, Vec<[f64; 3]>);
+
+// Triangulations of polygons, keyed by their feature, level of detail and position in the
+// multi surface
+pub(crate) type Triangulations = HashMap<(Id, LevelOfDetail, usize), Triangulation>;
+
+// A panic in another thread must not hide the output collected so far, so poisoned locks are
+// used as they are
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn into_inner<T>(mutex: Mutex<T>) -> T {
+    mutex.into_inner().unwrap_or_else(PoisonError::into_inner)
+}
+
+// The output of a converted top-level city object: its counts and manifest entry, and its node
+// of the binary glTF file and its city objects of the CityJSON file that are written per input
+// document
+#[derive(Debug, Default)]
+pub struct CityObjectOutput {
+    pub summary: ConversionSummary,
+    pub gltf_node: Option<GltfNode>,
+    pub cityjson: Option<CityJsonCityObjects>,
+}
+
+pub fn collect_building_geometries(
+    input_building: &mut Building,
+    building_features: &[CityObjectFeature],
+    options: &ConversionOptions,
+    crs: Option<&CrsTransformation>,
+) -> CityObjectOutput {
+    let building_id = &input_building.occupied_space.space.city_object.gml.id;
 
     let mut collector_1 = GeometryCollector::new();
     input_building.accept(&mut collector_1);
 
-    // Prepare optional shared accumulators
-    let groups_by_class: Option<Arc<Mutex<HashMap<String, SurfaceGroup>>>> = if group_by_surface {
-        Some(Arc::new(Mutex::new(HashMap::new())))
-    } else {
+    // ecitygml collects the GroundSurfaces with the class of a WallSurface
+    for ground_surface in &input_building.ground_surface {
+        if let Some(collected_geometry) = collector_1
+            .city_objects
+            .get_mut(&ground_surface.thematic_surface.city_object.gml.id)
+        {
+            collected_geometry.class = CityObjectClass::GroundSurface;
+        }
+    }
+
+    collect_feature_geometries(
+        building_id,
+        city_object_class_to_str(CityObjectClass::Building),
+        collector_1.city_objects,
+        building_features,
+        options,
+        crs,
+    )
+}
+
+// Converts a top-level city object other than a building that is read by ecitygml (city
+// furniture, roads and solitary vegetation objects)
+pub fn collect_city_object_geometries(
+    city_object: &impl Visitable,
+    city_object_id: &Id,
+    class: CityObjectClass,
+    members: &[CityObjectFeature],
+    options: &ConversionOptions,
+    crs: Option<&CrsTransformation>,
+) -> CityObjectOutput {
+    let mut collector = GeometryCollector::new();
+    city_object.accept(&mut collector);
+
+    collect_feature_geometries(
+        city_object_id,
+        city_object_class_to_str(class),
+        collector.city_objects,
+        members,
+        options,
+        crs,
+    )
+}
+
+// Converts a top-level city object that ecitygml does not read (e.g. a bridge or a water body)
+pub fn collect_parsed_city_object_geometries(
+    city_object: &ParsedCityObject,
+    options: &ConversionOptions,
+    crs: Option<&CrsTransformation>,
+) -> CityObjectOutput {
+    collect_feature_geometries(
+        &city_object.id,
+        city_object.class_name,
+        HashMap::new(),
+        &city_object.members,
+        options,
+        crs,
+    )
+}
+
+// Converts the collected geometries of a top-level city object and all of its members. The
+// top-level city object takes the place of the building in the file names and the metadata.
+// Errors are reported per city object; the city object counts as failed if any of its files
+// could not be written.
+fn collect_feature_geometries(
+    city_object_id: &Id,
+    city_object_class: &str,
+    mut city_objects: HashMap<Id, CityObjectGeometryCollection>,
+    members: &[CityObjectFeature],
+    options: &ConversionOptions,
+    crs: Option<&CrsTransformation>,
+) -> CityObjectOutput {
+    // Features that ecitygml does not read at all (building parts, installations, ...) or reads
+    // without their parent are added or replace the collected ones
+    let mut feature_parents: HashMap<Id, (&str, &str)> = HashMap::new();
+    for feature in members {
+        let feature_id = feature.geometry.gml.id.clone();
+        feature_parents.insert(
+            feature_id.clone(),
+            (feature.parent_id.as_str(), feature.class_name),
+        );
+        city_objects.insert(feature_id, feature.geometry.clone());
+    }
+
+    // All vertices are reprojected first, so the envelope, the bounding box and the translation
+    // are derived in the target CRS
+    let transform_point = |point: [f64; 3]| match crs {
+        Some(crs) => crs.transform_point(point),
+        None => Some(point),
+    };
+    if crs.is_some_and(|crs| !crs.is_identity()) {
+        transform_city_objects(city_objects.values_mut(), &transform_point);
+    }
+
+    // Only the geometry of a single level of detail is converted, if levels of detail are given
+    let selected_lod = if options.lods.is_empty() {
         None
+    } else {
+        let available_lod = options.lods.iter().find(|lod| {
+            city_objects
+                .values()
+                .any(|city_object| city_object.multi_surfaces.contains_key(lod))
+        });
+        match available_lod {
+            Some(lod) => Some(*lod),
+            None => {
+                let warning = format!(
+                    "{} {} has no geometry in any of the requested levels of detail.",
+                    city_object_class, city_object_id
+                );
+                println!("{}", warning);
+                return CityObjectOutput {
+                    summary: ConversionSummary {
+                        city_objects_skipped: 1,
+                        city_objects: vec![CityObjectManifest {
+                            building_id: city_object_id.to_string(),
+                            class: city_object_class.to_string(),
+                            source: options.source.clone(),
+                            warnings: vec![warning],
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                    gltf_node: None,
+                    cityjson: None,
+                };
+            }
+        }
     };
+    let lod_number = selected_lod.map(level_of_detail_to_number);
+    let file_stem = format!("{}{}", city_object_id, lod_suffix(lod_number));
 
-    let groups_by_semantic_surface: Option<Arc<Mutex<HashMap<String, SurfaceGroup>>>> =
-        if group_by_semantic_surface {
-            Some(Arc::new(Mutex::new(HashMap::new())))
-        } else {
-            None
-        };
+    let mut warnings = Vec::new();
 
-    collector_1
-        .city_objects
-        .par_iter()
-        .for_each(|collected_geometry| {
-            let gml_id = &collected_geometry.1.gml.id;
-            let class = collected_geometry.1.class;
-            let multi_surfaces = &collected_geometry.1.multi_surfaces;
-
-            let class_key = city_object_class_to_str(class).to_owned();
-
-            for multi_surface in multi_surfaces {
-                process_multi_surface(
-                    &multi_surface,
-                    building_id,
-                    class,
-                    dx,
-                    dy,
-                    dz,
-                    &bbox,
-                    gml_id,
-                    groups_by_class.clone(),
-                    groups_by_semantic_surface.clone(),
-                    class_key.clone(),
+    // All vertices are translated into the local CRS before the envelope and the bounding box
+    // are derived, so every output is written in the same local coordinates
+    let mut translation = [0.0; 3];
+    if options.tbw {
+        match local_origin(
+            options.origin,
+            &city_objects,
+            &feature_parents,
+            selected_lod,
+        ) {
+            Some(origin) => {
+                translation = origin.map(|c| -c);
+                transform_city_objects(city_objects.values_mut(), &|point| {
+                    Some(translate(point, translation))
+                });
+            }
+            None => {
+                let warning = format!(
+                    "{} {} has no vertices to derive a local origin from.",
+                    city_object_class, city_object_id
                 );
+                println!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+    }
+    let [dx, dy, dz] = translation;
+
+    let envelope = collected_envelope(city_objects.values(), selected_lod);
+
+    // Initialize an empty bounding box
+    let mut bbox = (Vec::new(), Vec::new());
+
+    // Distinguish the different cases of the bounding box. An imported bounding box takes
+    // precedence over the one derived from the envelope of the city object.
+    if let Some(imported_bboxes) = &options.imported_bboxes {
+        match import_bounding_box(&city_object_id.to_string(), imported_bboxes, &|point| {
+            transform_point(point).map(|point| translate(point, translation))
+        }) {
+            Some(imported_bbox) => bbox = imported_bbox,
+            None if options.add_bb => {
+                warnings.push(format!(
+                    "No imported bounding box for {} {}, using its envelope instead.",
+                    city_object_class, city_object_id
+                ));
+                bbox = construct_buffered_bounding_box(envelope.as_ref());
             }
+            None => warnings.push(format!(
+                "No imported bounding box for {} {}.",
+                city_object_class, city_object_id
+            )),
+        }
+        if let Some(warning) = warnings.last() {
+            println!("{}", warning);
+        }
+    } else if options.add_bb {
+        bbox = construct_buffered_bounding_box(envelope.as_ref());
+    }
+
+    // Polygons facing into their solid are turned around before any output is written
+    let (flipped_polygons, mut triangulations) = if options.repair_orientation {
+        repair_orientation(
+            city_object_id,
+            &mut city_objects,
+            &feature_parents,
+            selected_lod,
+            options.triangulator,
+        )
+    } else {
+        Default::default()
+    };
+    if !flipped_polygons.is_empty() {
+        println!(
+            "Reversed {} inward-facing polygons of {} {}.",
+            flipped_polygons.len(),
+            city_object_class,
+            city_object_id
+        );
+    }
+
+    // CityJSON vertices follow the axes and units of the CRS, so the CityJSON file is written
+    // from the geometry before the output transform
+    let untransformed_city_objects = (options.cityjson.is_some()
+        && !options.output_transform.is_identity())
+    .then(|| city_objects.clone());
+
+    // The bounding box is derived in metres, so its buffer and reflectors keep their size, and
+    // then transformed together with the geometry into the written coordinates
+    let output_transform = &options.output_transform;
+    let transform_matrix = output_transform.matrix(translation);
+    if !output_transform.is_identity() {
+        transform_city_objects(city_objects.values_mut(), &|point| {
+            Some(output_transform.apply(point))
         });
+        for vertex in &mut bbox.0 {
+            *vertex = output_transform.apply(*vertex);
+        }
+        for (_, points) in triangulations.values_mut() {
+            for point in points {
+                *point = output_transform.apply(*point);
+            }
+        }
+    }
+
+    // The whole-city-object output is assembled from the same per-surface groups
+    let shared = SharedOutput {
+        triangulations,
+        groups_by_class: options.group_sc.then(Default::default),
+        groups_by_semantic_surface: (options.group_scomp
+            || options.group_building
+            || options.write_glb
+            || options.cityjson.is_some())
+        .then(Default::default),
+        warnings: Mutex::new(warnings),
+        ..Default::default()
+    };
+
+    city_objects.par_iter().for_each(|collected_geometry| {
+        let gml_id = &collected_geometry.1.gml.id;
+        let class = collected_geometry.1.class;
+        let multi_surfaces = collected_geometry
+            .1
+            .multi_surfaces
+            .iter()
+            .filter(|(lod, _)| selected_lod.is_none_or(|selected| **lod == selected));
+
+        let (parent_id, class_key) = match feature_parents.get(gml_id) {
+            Some((parent_id, class_name)) => (parent_id.to_string(), class_name.to_string()),
+            None => (
+                city_object_id.to_string(),
+                city_object_class_to_str(class).to_owned(),
+            ),
+        };
+
+        for multi_surface in multi_surfaces {
+            process_multi_surface(
+                &multi_surface,
+                &bbox,
+                options,
+                gml_id,
+                &parent_id,
+                &shared,
+                class_key.clone(),
+                lod_suffix(lod_number),
+            );
+        }
+    });
+
+    let SharedOutput {
+        triangulations: _,
+        groups_by_class,
+        groups_by_semantic_surface,
+        file_metadata,
+        counts,
+        warnings,
+        errors,
+        polygons_failed,
+    } = shared;
+    let mut file_metadata = into_inner(file_metadata);
+    let mut errors = into_inner(errors);
+    let mut gltf_node = None;
+    let mut cityjson = None;
+    let mut record = |result: Result<FileMetadata>| match result {
+        Ok(metadata) => file_metadata.push(metadata),
+        Err(e) => errors.push(e),
+    };
 
     //  Write grouped OBJ files (semantic class level)
-    if let Some(groups_arc) = groups_by_class {
-        let map = Arc::try_unwrap(groups_arc)
-            .expect("Unexpected Arc reference count")
-            .into_inner()
-            .unwrap();
+    if let Some(groups) = groups_by_class {
+        let map = into_inner(groups);
 
         for (class_key, group) in map {
-            let filename = format!("{}_{}.obj", building_id, class_key);
-
-            write_obj_file(
-                group.vertices,
-                group.triangles,
-                building_id,
-                write_functions::SemanticSurfaceId::Str(&filename),
-                &class_key,
-                dx,
-                dy,
-                dz,
-                &bbox,
-                &Id::from_hashed_string("grouped"),
-                &Id::from_hashed_string("grouped"),
+            let filename = format!("{}_{}{}", city_object_id, class_key, lod_suffix(lod_number));
+            let metadata = group.file_metadata(format!("{}.obj", filename), &class_key);
+            let normals = options.normals.map(|normals| group.vertex_normals(normals));
+
+            record(
+                write_obj_file(
+                    group.vertices,
+                    normals.as_deref(),
+                    group.triangles,
+                    SemanticSurfaceId::Str(&filename),
+                    &bbox,
+                    options
+                        .material_palette
+                        .as_ref()
+                        .map(|palette| material_name(palette, &class_key)),
+                    &options.output,
+                )
+                .map(|_| metadata),
             );
         }
     }
 
     // Write grouped OBJ files (semantic surface level)
-    if let Some(groups_arc) = groups_by_semantic_surface {
-        let map = Arc::try_unwrap(groups_arc)
-            .expect("Unexpected Arc reference count")
-            .into_inner()
-            .unwrap();
-
-        for (surface_id, group) in map {
-            let class_name = group.class_name.as_deref().unwrap_or("UnknownSurface");
-            let filename = format!("{}_{}_{}", building_id, class_name, surface_id);
-            println!("filename {}", class_name);
-            write_obj_file(
-                group.vertices,
-                group.triangles,
-                building_id,
-                write_functions::SemanticSurfaceId::Str(&filename),
-                &class_name,
+    if let Some(groups) = groups_by_semantic_surface {
+        let map = into_inner(groups);
+
+        // Write one OBJ file for the whole city object with one object per semantic surface
+        if options.group_building {
+            record(write_city_object_groups(
+                city_object_id,
+                &file_stem,
+                city_object_class,
+                &map,
+                &bbox,
+                options,
+            ));
+        }
+
+        // Add a node for the whole city object with one primitive per semantic surface to the
+        // binary glTF file of the input document
+        if options.write_glb {
+            record(
+                city_object_glb_node(city_object_id, city_object_class, &map, &bbox, options).map(
+                    |(metadata, node)| {
+                        gltf_node = Some(node);
+                        metadata
+                    },
+                ),
+            );
+        }
+
+        for (surface_id, group) in map.into_iter().filter(|_| options.group_scomp) {
+            let class_name = group
+                .class_name
+                .clone()
+                .unwrap_or("UnknownSurface".to_string());
+            let filename = format!(
+                "{}_{}_{}{}",
+                city_object_id,
+                class_name,
+                surface_id,
+                lod_suffix(lod_number)
+            );
+            let metadata = group.file_metadata(format!("{}.obj", filename), &class_name);
+            let normals = options.normals.map(|normals| group.vertex_normals(normals));
+
+            record(
+                write_obj_file(
+                    group.vertices,
+                    normals.as_deref(),
+                    group.triangles,
+                    SemanticSurfaceId::Str(&filename),
+                    &bbox,
+                    options
+                        .material_palette
+                        .as_ref()
+                        .map(|palette| material_name(palette, &class_name)),
+                    &options.output,
+                )
+                .map(|_| metadata),
+            );
+        }
+    }
+
+    // Add the city object with its features and semantic surfaces to the CityJSON file of the
+    // input document
+    if let Some(geometry_type) = options.cityjson {
+        record(
+            city_object_cityjson(
+                city_object_id,
+                city_object_class,
+                untransformed_city_objects.as_ref().unwrap_or(&city_objects),
+                &feature_parents,
+                selected_lod,
+                translation,
+                geometry_type,
+                options,
+            )
+            .map(|(metadata, city_objects)| {
+                cityjson = Some(city_objects);
+                metadata
+            }),
+        );
+    }
+
+    file_metadata.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    let mut files: Vec<String> = file_metadata.iter().map(|f| f.file_name.clone()).collect();
+
+    if options.add_json {
+        files.push(format!("{}.json", file_stem));
+        let building_metadata = BuildingMetadata {
+            building_id: city_object_id.to_string(),
+            class: city_object_class.to_string(),
+            source: options.source.clone(),
+            crs: crs.map(|crs| format!("EPSG:{}", crs.target_epsg)),
+            lod: lod_number,
+            dx,
+            dy,
+            dz,
+            transform: transform_matrix,
+            flipped_polygons: flipped_polygons.clone(),
+            files: file_metadata,
+        };
+        if let Err(e) = write_json_metadata(&building_metadata, &options.output) {
+            errors.push(e);
+        }
+    }
+
+    for e in &errors {
+        eprintln!(
+            "Failed to convert {} {}: {}",
+            city_object_class, city_object_id, e
+        );
+    }
+    CityObjectOutput {
+        summary: ConversionSummary {
+            city_objects_converted: usize::from(errors.is_empty()),
+            city_objects_failed: usize::from(!errors.is_empty()),
+            polygons_failed: polygons_failed.into_inner(),
+            city_objects: vec![CityObjectManifest {
+                building_id: city_object_id.to_string(),
+                class: city_object_class.to_string(),
+                source: options.source.clone(),
+                crs: crs.map(|crs| format!("EPSG:{}", crs.target_epsg)),
+                lod: lod_number,
                 dx,
                 dy,
                 dz,
-                &bbox,
-                &Id::from_hashed_string("grouped"),
-                &Id::from_hashed_string(&filename),
+                transform: transform_matrix,
+                flipped_polygons,
+                files,
+                counts: into_inner(counts),
+                warnings: into_inner(warnings),
+                errors: errors.iter().map(Error::to_string).collect(),
+            }],
+            ..Default::default()
+        },
+        gltf_node,
+        cityjson,
+    }
+}
+
+// The origin of the local coordinates of a city object with --tbw, derived from the geometry
+// of the converted level of detail
+fn local_origin(
+    origin: LocalOrigin,
+    city_objects: &HashMap<Id, CityObjectGeometryCollection>,
+    feature_parents: &HashMap<Id, (&str, &str)>,
+    selected_lod: Option<LevelOfDetail>,
+) -> Option<[f64; 3]> {
+    fn multi_surfaces(
+        city_object: &CityObjectGeometryCollection,
+        selected_lod: Option<LevelOfDetail>,
+    ) -> impl Iterator<Item = &MultiSurface> {
+        city_object
+            .multi_surfaces
+            .iter()
+            .filter(move |(lod, _)| selected_lod.is_none_or(|selected| **lod == selected))
+            .map(|(_, multi_surface)| multi_surface)
+    }
+    let envelope = || collected_envelope(city_objects.values(), selected_lod);
+
+    match origin {
+        LocalOrigin::EnvelopeCenter => envelope().map(|envelope| envelope_center(&envelope)),
+        LocalOrigin::EnvelopeLower => envelope().map(|envelope| envelope.lower_corner().coords()),
+        LocalOrigin::VertexCentroid => vertex_centroid(
+            city_objects
+                .values()
+                .flat_map(|city_object| multi_surfaces(city_object, selected_lod)),
+        ),
+        LocalOrigin::GroundCentroid => {
+            let ground_surfaces = city_objects
+                .values()
+                .filter(|city_object| {
+                    let class_name = match feature_parents.get(&city_object.gml.id) {
+                        Some((_, class_name)) => class_name,
+                        None => city_object_class_to_str(city_object.class),
+                    };
+                    class_name == "GroundSurface"
+                })
+                .flat_map(|city_object| multi_surfaces(city_object, selected_lod));
+            vertex_centroid(ground_surfaces).or_else(|| {
+                let envelope = envelope()?;
+                let [x, y, _] = envelope_center(&envelope);
+                Some([x, y, envelope.lower_corner().z()])
+            })
+        }
+        LocalOrigin::Fixed(origin) => Some(origin),
+    }
+}
+
+// Reverses the polygons that face into the solid they belong to and returns their gml:ids. The
+// solid of a feature is the closest ancestor that is not a surface, so e.g. the walls, roofs
+// and windows of a building part are tested against each other, but not against the
+// installations of the building. The polygons are triangulated with the triangulator of the
+// output, and the triangulations are returned for it, with the triangles of reversed polygons
+// turned around as well.
+fn repair_orientation(
+    city_object_id: &Id,
+    city_objects: &mut HashMap<Id, CityObjectGeometryCollection>,
+    feature_parents: &HashMap<Id, (&str, &str)>,
+    selected_lod: Option<LevelOfDetail>,
+    triangulator: Triangulator,
+) -> (Vec<String>, Triangulations) {
+    let city_object_id = city_object_id.to_string();
+    let features: HashMap<String, (String, &str)> = city_objects
+        .iter()
+        .map(|(id, city_object)| {
+            let feature = match feature_parents.get(id) {
+                Some((parent_id, class_name)) => (parent_id.to_string(), *class_name),
+                None => (
+                    city_object_id.clone(),
+                    city_object_class_to_str(city_object.class),
+                ),
+            };
+            (id.to_string(), feature)
+        })
+        .collect();
+    let solid_of = |id: &str| -> String {
+        let mut current = id;
+        for _ in 0..=features.len() {
+            match features.get(current) {
+                Some((parent_id, class_name))
+                    if class_name.ends_with("Surface") && parent_id != current =>
+                {
+                    current = parent_id
+                }
+                _ => break,
+            }
+        }
+        current.to_string()
+    };
+
+    // The polygons of every solid and level of detail, in a fixed order
+    let mut ids: Vec<Id> = city_objects.keys().cloned().collect();
+    ids.sort_by_key(|id| id.to_string());
+    let mut solids: HashMap<(String, LevelOfDetail), Vec<Option<RayTarget>>> = HashMap::new();
+    let mut members = Vec::new();
+    for id in ids {
+        let solid = solid_of(&id.to_string());
+        let mut lods: Vec<LevelOfDetail> = city_objects[&id]
+            .multi_surfaces
+            .keys()
+            .filter(|lod| selected_lod.is_none_or(|selected| **lod == selected))
+            .copied()
+            .collect();
+        lods.sort_by_key(|lod| level_of_detail_to_number(*lod));
+        for lod in lods {
+            let targets = solids.entry((solid.clone(), lod)).or_default();
+            let first_index = targets.len();
+            targets.extend(
+                city_objects[&id].multi_surfaces[&lod]
+                    .surface_member()
+                    .iter()
+                    .map(RayTarget::new),
             );
+            members.push((id.clone(), lod, solid.clone(), first_index));
+        }
+    }
+
+    let mut flipped_polygons = Vec::new();
+    let mut triangulations = Triangulations::new();
+    for (id, lod, solid, first_index) in members {
+        let targets = &solids[&(solid, lod)];
+        let Some(city_object) = city_objects.get_mut(&id) else {
+            continue;
+        };
+        let Some(multi_surface) = city_object.multi_surfaces.get(&lod) else {
+            continue;
+        };
+
+        // The reversed polygon (if it faced inwards) and the triangulation of every polygon
+        let repaired: Vec<(Option<Polygon>, Triangulation)> = multi_surface
+            .surface_member()
+            .par_iter()
+            .enumerate()
+            .map(|(i, polygon)| {
+                let (mut triangles, points) = triangulate(polygon, triangulator);
+                let other_polygons = targets
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != first_index + i)
+                    .filter_map(|(_, target)| target.as_ref());
+                if !faces_inward(&triangles, &points, other_polygons) {
+                    return (None, (triangles, points));
+                }
+                let reversed = reverse_polygon(polygon);
+                if reversed.is_some() {
+                    for triangle in triangles.chunks_exact_mut(3) {
+                        triangle.swap(1, 2);
+                    }
+                }
+                (reversed, (triangles, points))
+            })
+            .collect();
+
+        let mut polygons = Vec::with_capacity(repaired.len());
+        let mut flipped = false;
+        for (i, (polygon, (reversed, triangulation))) in multi_surface
+            .surface_member()
+            .iter()
+            .zip(repaired)
+            .enumerate()
+        {
+            triangulations.insert((id.clone(), lod, i), triangulation);
+            match reversed {
+                Some(reversed) => {
+                    flipped = true;
+                    flipped_polygons.push(polygon.gml.id.to_string());
+                    polygons.push(reversed);
+                }
+                None => polygons.push(polygon.clone()),
+            }
+        }
+        if flipped && let Ok(repaired) = MultiSurface::new(multi_surface.gml.clone(), polygons) {
+            city_object.multi_surfaces.insert(lod, repaired);
         }
     }
+    flipped_polygons.sort();
+    (flipped_polygons, triangulations)
+}
 
-    if add_json {
-        // TODO: muss noch implementiert werden
+fn translate(point: [f64; 3], translation: [f64; 3]) -> [f64; 3] {
+    [
+        point[0] + translation[0],
+        point[1] + translation[1],
+        point[2] + translation[2],
+    ]
+}
+
+// Writes all semantic surfaces of a city object into <file_stem>.obj, using one object per
+// thematic surface (named after its gml:id) in a group named after its class
+fn write_city_object_groups(
+    building_id: &Id,
+    file_stem: &str,
+    class: &str,
+    groups: &HashMap<String, SurfaceGroup>,
+    bbox: &BoundingBoxGeometry,
+    options: &ConversionOptions,
+) -> Result<FileMetadata> {
+    // No empty file is written, like for the glb and CityJSON output
+    if groups.values().all(|group| group.triangles.is_empty()) {
+        return Err(Error::NoGeometry(building_id.to_string()));
     }
+
+    let mut surface_ids: Vec<&String> = groups.keys().collect();
+    surface_ids.sort();
+
+    let mut metadata = FileMetadata {
+        file_name: format!("{}.obj", file_stem),
+        class: class.to_string(),
+        parent_gml_ids: Vec::new(),
+        thematic_surface_gml_ids: Vec::new(),
+        multi_surface_gml_ids: Vec::new(),
+        polygon_gml_ids: Vec::new(),
+    };
+
+    let group_normals: Vec<Option<Vec<[f64; 3]>>> = surface_ids
+        .iter()
+        .map(|surface_id| {
+            options
+                .normals
+                .map(|normals| groups[*surface_id].vertex_normals(normals))
+        })
+        .collect();
+    let obj_groups: Vec<ObjGroup> = surface_ids
+        .into_iter()
+        .zip(&group_normals)
+        .map(|(surface_id, normals)| {
+            let group = &groups[surface_id];
+            for parent_id in &group.parent_ids {
+                push_unique(&mut metadata.parent_gml_ids, parent_id);
+            }
+            metadata
+                .thematic_surface_gml_ids
+                .extend(group.thematic_surface_ids.iter().cloned());
+            metadata
+                .multi_surface_gml_ids
+                .extend(group.multi_surface_ids.iter().cloned());
+            metadata
+                .polygon_gml_ids
+                .extend(group.polygon_ids.iter().cloned());
+
+            ObjGroup {
+                object_name: surface_id,
+                group_name: group.class_name.as_deref().unwrap_or("UnknownSurface"),
+                vertices: &group.vertices,
+                normals: normals.as_deref(),
+                triangles: &group.triangles,
+            }
+        })
+        .collect();
+
+    write_grouped_obj_file(
+        &obj_groups,
+        file_stem,
+        bbox,
+        options.material_palette.as_ref(),
+        &options.output,
+    )
+    .map(|_| metadata)
 }
 
-pub fn process_multi_surface(
-    input_multi_surface: &(&LevelOfDetail, &MultiSurface),
+fn city_object_glb_node(
     building_id: &Id,
-    class: CityObjectClass,
-    dx: f64,
-    dy: f64,
-    dz: f64,
-    bbox: &(Vec<[f64; 3]>, Vec<[u64; 3]>),
+    class: &str,
+    groups: &HashMap<String, SurfaceGroup>,
+    bbox: &BoundingBoxGeometry,
+    options: &ConversionOptions,
+) -> Result<(FileMetadata, GltfNode)> {
+    let mut surface_ids: Vec<&String> = groups.keys().collect();
+    surface_ids.sort();
+
+    let primitives: Vec<GltfPrimitive> = surface_ids
+        .iter()
+        .map(|surface_id| {
+            let group = &groups[*surface_id];
+            GltfPrimitive {
+                name: surface_id,
+                class: group.class_name.as_deref().unwrap_or("UnknownSurface"),
+                vertices: &group.vertices,
+                triangles: &group.triangles,
+                parent_gml_ids: &group.parent_ids,
+                multi_surface_gml_ids: &group.multi_surface_ids,
+                polygon_gml_ids: &group.polygon_ids,
+            }
+        })
+        .collect();
+
+    let node = gltf_node(
+        &building_id.to_string(),
+        &primitives,
+        bbox,
+        options.output_transform.up_axis,
+    )?;
+
+    let mut parent_gml_ids = Vec::new();
+    for parent_id in surface_ids.iter().flat_map(|id| &groups[*id].parent_ids) {
+        push_unique(&mut parent_gml_ids, parent_id);
+    }
+
+    let metadata = FileMetadata {
+        file_name: format!("{}.glb", document_file_stem(options)),
+        class: class.to_string(),
+        parent_gml_ids,
+        thematic_surface_gml_ids: surface_ids.iter().map(|id| id.to_string()).collect(),
+        multi_surface_gml_ids: surface_ids
+            .iter()
+            .flat_map(|id| groups[*id].multi_surface_ids.iter().cloned())
+            .collect(),
+        polygon_gml_ids: surface_ids
+            .iter()
+            .flat_map(|id| groups[*id].polygon_ids.iter().cloned())
+            .collect(),
+    };
+    Ok((metadata, node))
+}
+
+// Name of the binary glTF and CityJSON files all city objects of the input document are written
+// to: the file name of the source without its extensions (e.g. 1340 for 1340.gml.gz)
+pub fn document_file_stem(options: &ConversionOptions) -> String {
+    options
+        .source
+        .as_deref()
+        .and_then(|source| Path::new(source).file_name())
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or("city_model")
+        .to_string()
+}
+
+// Converts the city object for the CityJSON file of the input document. The centre of its
+// envelope is placed at its position in the (target) CRS, i.e. without the building-wise
+// translation, so the city object keeps its real-world location.
+#[allow(clippy::too_many_arguments)]
+fn city_object_cityjson(
+    city_object_id: &Id,
+    class: &str,
+    city_objects: &HashMap<Id, CityObjectGeometryCollection>,
+    feature_parents: &HashMap<Id, (&str, &str)>,
+    selected_lod: Option<LevelOfDetail>,
+    translation: [f64; 3],
+    geometry_type: CityJsonGeometry,
+    options: &ConversionOptions,
+) -> Result<(FileMetadata, CityJsonCityObjects)> {
+    let city_object_id_string = city_object_id.to_string();
+
+    let features: Vec<CityJsonFeature> = city_objects
+        .iter()
+        .map(|(id, collected_geometry)| {
+            let (parent_id, class_name) = feature_parents.get(id).copied().unwrap_or((
+                city_object_id_string.as_str(),
+                city_object_class_to_str(collected_geometry.class),
+            ));
+            CityJsonFeature {
+                id: id.to_string(),
+                class_name,
+                parent_id,
+                multi_surfaces: collected_geometry
+                    .multi_surfaces
+                    .iter()
+                    .filter(|(lod, _)| selected_lod.is_none_or(|selected| **lod == selected))
+                    .map(|(lod, multi_surface)| (*lod, multi_surface))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let center = collected_envelope(city_objects.values(), selected_lod)
+        .map_or([0.0; 3], |envelope| envelope_center(&envelope));
+
+    let cityjson = cityjson_city_objects(
+        &city_object_id_string,
+        class,
+        &features,
+        center,
+        [0, 1, 2].map(|axis| center[axis] - translation[axis]),
+        geometry_type,
+        options.triangulator,
+    )?;
+
+    let mut metadata = FileMetadata {
+        file_name: format!("{}.city.json", document_file_stem(options)),
+        class: class.to_string(),
+        parent_gml_ids: Vec::new(),
+        thematic_surface_gml_ids: Vec::new(),
+        multi_surface_gml_ids: Vec::new(),
+        polygon_gml_ids: Vec::new(),
+    };
+    let mut features = features;
+    features.sort_by(|a, b| a.id.cmp(&b.id));
+    for feature in features.iter().filter(|f| !f.multi_surfaces.is_empty()) {
+        push_unique(&mut metadata.parent_gml_ids, feature.parent_id);
+        metadata.thematic_surface_gml_ids.push(feature.id.clone());
+        for (_, multi_surface) in &feature.multi_surfaces {
+            push_unique(&mut metadata.multi_surface_gml_ids, &multi_surface.gml.id);
+            metadata.polygon_gml_ids.extend(
+                multi_surface
+                    .surface_member()
+                    .iter()
+                    .map(|polygon| polygon.gml.id.to_string()),
+            );
+        }
+    }
+    Ok((metadata, cityjson))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn process_multi_surface(
+    input_multi_surface: &(&LevelOfDetail, &MultiSurface),
+    bbox: &BoundingBoxGeometry,
+    options: &ConversionOptions,
     gml_id: &Id,
-    groups_by_class: Option<Arc<Mutex<HashMap<String, SurfaceGroup>>>>,
-    groups_by_semantic_surface: Option<Arc<Mutex<HashMap<String, SurfaceGroup>>>>,
+    parent_id: &str,
+    shared: &SharedOutput,
     class_key: String,
+    file_suffix: String,
 ) {
     let stuffs = &input_multi_surface.1.surface_member();
     let stuff_gml_id = &input_multi_surface.1.gml.id;
-    stuffs.par_iter().for_each(|surface_member| {
-        process_surface_member(
-            surface_member,
-            building_id,
-            stuff_gml_id,
-            class,
-            dx,
-            dy,
-            dz,
-            bbox,
-            gml_id,
-            class_key.clone(),
-            groups_by_class.clone(),
-            groups_by_semantic_surface.clone(),
-        );
-    });
+    let lod = *input_multi_surface.0;
+    stuffs
+        .par_iter()
+        .enumerate()
+        .for_each(|(index, surface_member)| {
+            process_surface_member(
+                surface_member,
+                shared.triangulations.get(&(gml_id.clone(), lod, index)),
+                stuff_gml_id,
+                bbox,
+                options,
+                gml_id,
+                parent_id,
+                class_key.clone(),
+                shared,
+                &file_suffix,
+            );
+        });
 }
 
-pub fn process_surface_member(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn process_surface_member(
     input_surface_member: &Polygon,
-    building_id: &Id,
+    triangulation: Option<&Triangulation>,
     multi_surface_id: &Id,
-    thematic_info: CityObjectClass,
-    dx: f64,
-    dy: f64,
-    dz: f64,
-    bbox: &(Vec<[f64; 3]>, Vec<[u64; 3]>),
+    bbox: &BoundingBoxGeometry,
+    options: &ConversionOptions,
     gml_id: &Id,
+    parent_id: &str,
     class_key: String,
-    groups_by_class: Option<Arc<Mutex<HashMap<String, SurfaceGroup>>>>,
-    groups_by_semantic_surface: Option<Arc<Mutex<HashMap<String, SurfaceGroup>>>>,
+    shared: &SharedOutput,
+    file_suffix: &str,
 ) {
-    let (triangles, all_points) = triangulate(input_surface_member);
+    let (triangles, all_points) = match triangulation {
+        Some(triangulation) => triangulation.clone(),
+        None => triangulate(input_surface_member, options.triangulator),
+    };
     let surface_id = input_surface_member.gml.id.clone();
 
-    // Semantic surface grouping
-    if let Some(groups_arc) = groups_by_semantic_surface {
-        let mut map = groups_arc.lock().unwrap();
+    // Polygons without triangles (e.g. degenerate or self-intersecting ones) are left out
+    if triangles.is_empty() {
+        let warning = format!("Polygon {} could not be triangulated.", surface_id);
+        eprintln!("{}", warning);
+        lock(&shared.warnings).push(warning);
+        shared.polygons_failed.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
 
-        // Create or fetch the group for this semantic surface
-        let bucket = map.entry(surface_id.to_string()).or_insert_with(|| {
-            let mut g = SurfaceGroup::default();
-            g.class_name = Some(class_key.clone());
-            g
-        });
+    let normals = match options.normals {
+        Some(_) => vec![polygon_normal(&triangles, &all_points); all_points.len()],
+        None => Vec::new(),
+    };
 
-        let vertex_offset = bucket.vertices.len() as u32;
-        bucket.vertices.extend_from_slice(&all_points);
+    *lock(&shared.counts).entry(class_key.clone()).or_default() += GeometryCounts {
+        polygons: 1,
+        triangles: triangles.len() / 3,
+        vertices: all_points.len(),
+    };
 
-        let shifted: Vec<u32> = triangles
-            .into_iter()
-            .map(|idx| idx + vertex_offset)
-            .collect();
-        bucket.triangles.extend(shifted);
-    }
-    // Existing: semantic class grouping
-    else if let Some(groups_arc) = groups_by_class {
-        let mut map = groups_arc.lock().unwrap();
-        let bucket = map.entry(class_key).or_default();
+    let grouped = shared.groups_by_semantic_surface.is_some() || shared.groups_by_class.is_some();
 
-        let vertex_offset = bucket.vertices.len() as u32;
-        bucket.vertices.extend_from_slice(&all_points);
+    // Semantic surface grouping
+    if let Some(groups) = &shared.groups_by_semantic_surface {
+        let mut map = lock(groups);
 
-        let shifted: Vec<u32> = triangles
-            .into_iter()
-            .map(|idx| idx + vertex_offset)
-            .collect();
-        bucket.triangles.extend(shifted);
+        // Create or fetch the group for this semantic surface
+        let bucket = map
+            .entry(gml_id.to_string())
+            .or_insert_with(|| SurfaceGroup {
+                class_name: Some(class_key.clone()),
+                ..Default::default()
+            });
+        bucket.add_polygon(
+            &triangles,
+            &all_points,
+            &normals,
+            parent_id,
+            gml_id,
+            multi_surface_id,
+            &surface_id,
+        );
+    }
+    // Existing: semantic class grouping
+    if let Some(groups) = &shared.groups_by_class {
+        let mut map = lock(groups);
+        let bucket = map.entry(class_key.clone()).or_default();
+        bucket.add_polygon(
+            &triangles,
+            &all_points,
+            &normals,
+            parent_id,
+            gml_id,
+            multi_surface_id,
+            &surface_id,
+        );
     }
     // per-polygon output
-    else {
-        let thematic_info_string = city_object_class_to_str(thematic_info);
-        write_obj_file(
+    if !grouped {
+        let file_stem = format!("{}{}", surface_id, file_suffix);
+        let normals: Option<Vec<[f64; 3]>> =
+            (!normals.is_empty()).then(|| normals.iter().map(|n| unit_normal(*n)).collect());
+        let written = write_obj_file(
             all_points,
+            normals.as_deref(),
             triangles,
-            building_id,
-            write_functions::SemanticSurfaceId::Id(&surface_id),
-            &thematic_info_string,
-            dx,
-            dy,
-            dz,
+            SemanticSurfaceId::Str(&file_stem),
             bbox,
-            gml_id,
-            multi_surface_id,
+            options
+                .material_palette
+                .as_ref()
+                .map(|palette| material_name(palette, &class_key)),
+            &options.output,
         );
+        if let Err(e) = written {
+            lock(&shared.errors).push(e);
+            return;
+        }
+
+        lock(&shared.file_metadata).push(FileMetadata {
+            file_name: format!("{}.obj", file_stem),
+            class: class_key,
+            parent_gml_ids: vec![parent_id.to_string()],
+            thematic_surface_gml_ids: vec![gml_id.to_string()],
+            multi_surface_gml_ids: vec![multi_surface_id.to_string()],
+            polygon_gml_ids: vec![surface_id.to_string()],
+        });
+    }
+}
+
+pub fn level_of_detail_to_number(lod: LevelOfDetail) -> u8 {
+    match lod {
+        LevelOfDetail::Zero => 0,
+        LevelOfDetail::One => 1,
+        LevelOfDetail::Two => 2,
+        LevelOfDetail::Three => 3,
+    }
+}
+
+pub fn level_of_detail_from_number(number: u8) -> Option<LevelOfDetail> {
+    match number {
+        0 => Some(LevelOfDetail::Zero),
+        1 => Some(LevelOfDetail::One),
+        2 => Some(LevelOfDetail::Two),
+        3 => Some(LevelOfDetail::Three),
+        _ => None,
     }
 }
 
@@ -327,3 +1278,132 @@ pub fn city_object_class_to_str(class: CityObjectClass) -> &'static str {
         CityObjectClass::WindowSurface => "WindowSurface",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cityjson_functions::parse_cityjson;
+
+    // Adds a triangle with the normal of its plane at each of its vertices
+    fn add_triangle(group: &mut SurfaceGroup, points: [[f64; 3]; 3], thematic_surface_id: &str) {
+        let triangles = [0, 1, 2];
+        let normal = polygon_normal(&triangles, &points);
+        let id = Id::try_from(thematic_surface_id).unwrap();
+        group.add_polygon(&triangles, &points, &[normal; 3], "B1", &id, &id, &id);
+    }
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for axis in 0..3 {
+            assert!(
+                (actual[axis] - expected[axis]).abs() < 1e-12,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_normals_are_averaged_within_a_thematic_surface_only() {
+        // Two roof faces meeting at a ridge along the y axis at x = 0 and a wall below the ridge
+        let ridge = [[0.0, 0.0, 1.0], [0.0, 1.0, 1.0]];
+        let mut group = SurfaceGroup::default();
+        add_triangle(&mut group, [[-1.0, 0.0, 0.0], ridge[0], ridge[1]], "Roof");
+        add_triangle(&mut group, [[1.0, 0.0, 0.0], ridge[1], ridge[0]], "Roof");
+        add_triangle(&mut group, [ridge[0], [0.0, 0.0, 0.0], ridge[1]], "Wall");
+
+        let flat = group.vertex_normals(Normals::Flat);
+        let smooth = group.vertex_normals(Normals::Smooth);
+
+        let half = 0.5f64.sqrt();
+        assert_close(flat[1], [-half, 0.0, half]);
+        assert_close(flat[4], [half, 0.0, half]);
+        // The roof faces share the ridge vertices, the wall keeps its own normal
+        assert_close(smooth[0], [-half, 0.0, half]);
+        for ridge_vertex in [1, 2, 4, 5] {
+            assert_close(smooth[ridge_vertex], [0.0, 0.0, 1.0]);
+        }
+        for wall_vertex in 6..9 {
+            assert_close(flat[wall_vertex], smooth[wall_vertex]);
+            assert_close(smooth[wall_vertex], [1.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn the_reversed_face_of_a_cube_is_turned_around() {
+        // A cube with outward-facing faces, except for the reversed top face (B1_0_1)
+        let document = r#"{
+            "type": "CityJSON",
+            "version": "2.0",
+            "transform": { "scale": [1.0, 1.0, 1.0], "translate": [0.0, 0.0, 0.0] },
+            "CityObjects": {
+                "B1": {
+                    "type": "Building",
+                    "geometry": [{
+                        "type": "MultiSurface",
+                        "lod": "2",
+                        "boundaries": [
+                            [[0, 3, 2, 1]], [[7, 6, 5, 4]], [[0, 1, 5, 4]],
+                            [[1, 2, 6, 5]], [[2, 3, 7, 6]], [[3, 0, 4, 7]]
+                        ]
+                    }]
+                }
+            },
+            "vertices": [
+                [0, 0, 0], [10, 0, 0], [10, 10, 0], [0, 10, 0],
+                [0, 0, 10], [10, 0, 10], [10, 10, 10], [0, 10, 10]
+            ]
+        }"#;
+        let parsed = parse_cityjson(document).unwrap();
+        let mut city_objects = HashMap::new();
+        let mut feature_parents = HashMap::new();
+        for member in &parsed["B1"].members {
+            let id = member.geometry.gml.id.clone();
+            feature_parents.insert(id.clone(), (member.parent_id.as_str(), member.class_name));
+            city_objects.insert(id, member.geometry.clone());
+        }
+
+        let (flipped, triangulations) = repair_orientation(
+            &parsed["B1"].id,
+            &mut city_objects,
+            &feature_parents,
+            None,
+            Triangulator::Earcut,
+        );
+
+        assert_eq!(flipped, ["B1_0_1"]);
+        let multi_surface = &city_objects[&parsed["B1"].id].multi_surfaces[&LevelOfDetail::Two];
+        for (index, polygon) in multi_surface.surface_member().iter().enumerate() {
+            let (triangles, points) = triangulate(polygon, Triangulator::Earcut);
+            let outward = sub(vertex_centroid_of(&points), [5.0, 5.0, 5.0]);
+            assert!(
+                dot(polygon_normal(&triangles, &points), outward) > 0.0,
+                "{}",
+                index
+            );
+
+            let (triangles, points) =
+                &triangulations[&(parsed["B1"].id.clone(), LevelOfDetail::Two, index)];
+            assert!(
+                dot(polygon_normal(triangles, points), outward) > 0.0,
+                "{}",
+                index
+            );
+        }
+    }
+
+    fn vertex_centroid_of(points: &[[f64; 3]]) -> [f64; 3] {
+        let sum = points
+            .iter()
+            .fold([0.0; 3], |sum, p| [0, 1, 2].map(|i| sum[i] + p[i]));
+        sum.map(|c| c / points.len() as f64)
+    }
+
+    fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|i| a[i] - b[i])
+    }
+
+    fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+        (0..3).map(|i| a[i] * b[i]).sum()
+    }
+}
@@ -0,0 +1,244 @@
+use crate::error_functions::{Error, Result};
+use crate::input_functions::{InputDocument, InputFormat};
+use proj4rs::proj::Proj;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use serde::Deserialize;
+
+const ETRS89: &str = "+ellps=GRS80 +towgs84=0,0,0,0,0,0,0";
+const DHDN: &str = "+ellps=bessel +towgs84=598.1,73.7,418.2,0.202,0.045,-2.455,6.7";
+
+// Offline proj definitions of the coordinate reference systems that can be converted between:
+// WGS84 and ETRS89 (geographic and geocentric/ECEF), WGS84 / UTM, ETRS89 / UTM (also with the
+// zone prefix, e.g. EPSG:4647) and DHDN / Gauss-Krüger
+pub fn proj_definition(epsg: u32) -> Option<String> {
+    let definition = match epsg {
+        4326 | 4979 => "+proj=longlat +datum=WGS84 +no_defs".to_string(),
+        4258 | 4937 => format!("+proj=longlat {} +no_defs", ETRS89),
+        4978 => "+proj=geocent +datum=WGS84 +units=m +no_defs".to_string(),
+        4936 => format!("+proj=geocent {} +units=m +no_defs", ETRS89),
+        32601..=32660 => format!(
+            "+proj=utm +zone={} +datum=WGS84 +units=m +no_defs",
+            epsg - 32600
+        ),
+        32701..=32760 => format!(
+            "+proj=utm +zone={} +south +datum=WGS84 +units=m +no_defs",
+            epsg - 32700
+        ),
+        25828..=25838 => format!(
+            "+proj=utm +zone={} {} +units=m +no_defs",
+            epsg - 25800,
+            ETRS89
+        ),
+        4647 => format!(
+            "+proj=tmerc +lat_0=0 +lon_0=9 +k=0.9996 +x_0=32500000 +y_0=0 {} +units=m +no_defs",
+            ETRS89
+        ),
+        5650 => format!(
+            "+proj=tmerc +lat_0=0 +lon_0=15 +k=0.9996 +x_0=33500000 +y_0=0 {} +units=m +no_defs",
+            ETRS89
+        ),
+        31466..=31469 => {
+            let zone = epsg - 31464;
+            format!(
+                "+proj=tmerc +lat_0=0 +lon_0={} +k=1 +x_0={} +y_0=0 {} +units=m +no_defs",
+                zone * 3,
+                zone * 1_000_000 + 500_000,
+                DHDN
+            )
+        }
+        _ => return None,
+    };
+    Some(definition)
+}
+
+// Reads the EPSG code from a CRS name as used in srsName attributes and CityJSON files, e.g.
+// "EPSG:25832", "urn:ogc:def:crs:EPSG::25832", "http://www.opengis.net/def/crs/EPSG/0/25832",
+// the AdV names "urn:adv:crs:ETRS89_UTM32" or "urn:adv:crs:DE_DHDN_3GK3" or a plain number.
+// Of compound CRSs (e.g. "EPSG:25832*EPSG:7837") the horizontal CRS is taken.
+pub fn parse_crs(name: &str) -> Option<u32> {
+    let name = name.trim();
+    if let Ok(epsg) = name.parse() {
+        return Some(epsg);
+    }
+
+    let upper = name.to_uppercase();
+    if let Some(adv_name) = upper.strip_prefix("URN:ADV:CRS:") {
+        let horizontal = adv_name.split(['*', ',']).next()?;
+        if let Some(zone) = horizontal.strip_prefix("ETRS89_UTM") {
+            return zone.parse::<u32>().ok().map(|zone| 25800 + zone);
+        }
+        if let Some(zone) = horizontal.strip_prefix("DE_DHDN_3GK") {
+            return zone.parse::<u32>().ok().map(|zone| 31464 + zone);
+        }
+        return match horizontal {
+            "ETRS89" | "ETRS89_LAT-LON" => Some(4258),
+            "WGS84" => Some(4326),
+            _ => None,
+        };
+    }
+
+    // The code is the last number of the first component naming an EPSG CRS; a version
+    // number (e.g. the "0" of .../EPSG/0/25832) comes before it
+    let component = upper.split(['*', ',']).find(|c| c.contains("EPSG"))?;
+    let (_, code) = component.split_once("EPSG")?;
+    code.split([':', '/'])
+        .rfind(|part| !part.is_empty())?
+        .parse()
+        .ok()
+}
+
+// Reads the CRS of a document: the first srsName of a CityGML document (usually the one of the
+// envelope of the city model) or the referenceSystem of the metadata of a CityJSON document
+pub fn detect_source_crs(document: &InputDocument) -> Option<u32> {
+    match document.format {
+        Some(InputFormat::CityJson) => cityjson_reference_system(&document.content),
+        // The metadata is part of the header in the first line
+        Some(InputFormat::CityJsonSeq) => {
+            cityjson_reference_system(document.content.lines().next()?)
+        }
+        Some(InputFormat::Citygml) | None => {
+            let mut reader = Reader::from_str(&document.content);
+            loop {
+                match reader.read_event() {
+                    Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                        let srs_name = e
+                            .attributes()
+                            .flatten()
+                            .find(|a| a.key.local_name().as_ref() == b"srsName")
+                            .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()));
+                        if let Some(srs_name) = srs_name {
+                            return parse_crs(&srs_name);
+                        }
+                    }
+                    Ok(Event::Eof) | Err(_) => return None,
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+// Reads metadata.referenceSystem of a CityJSON object. All other members are skipped without
+// building them, so large files are not parsed into memory twice.
+fn cityjson_reference_system(json: &str) -> Option<u32> {
+    #[derive(Deserialize)]
+    struct CityJsonMetadata {
+        #[serde(rename = "referenceSystem")]
+        reference_system: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct CityJsonHeader {
+        metadata: Option<CityJsonMetadata>,
+    }
+
+    let header: CityJsonHeader = serde_json::from_str(json).ok()?;
+    parse_crs(&header.metadata?.reference_system?)
+}
+
+// Reprojection of coordinates from the CRS of the input into the target CRS. Geographic
+// coordinates are given as longitude, latitude and height in degrees. Heights are kept as they
+// are, as CityGML heights are usually normal heights, unless one of the CRSs is geocentric.
+// GML coordinates of geographic CRSs such as EPSG:4326 follow the EPSG axis order, i.e.
+// latitude first; with `latitude_first` they are swapped into longitude, latitude first.
+pub struct CrsTransformation {
+    pub source_epsg: u32,
+    pub target_epsg: u32,
+    source: Proj,
+    target: Proj,
+    swap_source_axes: bool,
+}
+
+impl CrsTransformation {
+    pub fn new(source_epsg: u32, target_epsg: u32, latitude_first: bool) -> Result<Self> {
+        let proj = |epsg: u32| -> Result<Proj> {
+            let definition = proj_definition(epsg)
+                .ok_or_else(|| Error::Crs(format!("EPSG:{} is not supported", epsg)))?;
+            Proj::from_proj_string(&definition)
+                .map_err(|e| Error::Crs(format!("EPSG:{}: {}", epsg, e)))
+        };
+
+        let source = proj(source_epsg)?;
+        Ok(Self {
+            source_epsg,
+            target_epsg,
+            swap_source_axes: latitude_first && source.is_latlong(),
+            source,
+            target: proj(target_epsg)?,
+        })
+    }
+
+    // True if nothing has to be converted
+    pub fn is_identity(&self) -> bool {
+        self.source_epsg == self.target_epsg && !self.swap_source_axes
+    }
+
+    pub fn transform_point(&self, [x, y, z]: [f64; 3]) -> Option<[f64; 3]> {
+        let [x, y] = if self.swap_source_axes {
+            [y, x]
+        } else {
+            [x, y]
+        };
+        if self.source_epsg == self.target_epsg {
+            return Some([x, y, z]);
+        }
+
+        let mut point = if self.source.is_latlong() {
+            (x.to_radians(), y.to_radians(), z)
+        } else {
+            (x, y, z)
+        };
+        proj4rs::transform::transform(&self.source, &self.target, &mut point).ok()?;
+        if self.target.is_latlong() {
+            point = (point.0.to_degrees(), point.1.to_degrees(), point.2);
+        }
+        if !self.source.is_geocent() && !self.target.is_geocent() {
+            point.2 = z;
+        }
+
+        [point.0, point.1, point.2]
+            .iter()
+            .all(|c| c.is_finite())
+            .then_some([point.0, point.1, point.2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epsg_codes_are_read_from_crs_names() {
+        for (name, epsg) in [
+            ("25832", Some(25832)),
+            (" EPSG:25832 ", Some(25832)),
+            ("urn:ogc:def:crs:EPSG::25832", Some(25832)),
+            ("http://www.opengis.net/def/crs/EPSG/0/25832", Some(25832)),
+            ("https://www.opengis.net/def/crs/EPSG/0/4979", Some(4979)),
+            ("EPSG:25832*EPSG:7837", Some(25832)),
+            (
+                "urn:ogc:def:crs,crs:EPSG::25833,crs:EPSG::7837",
+                Some(25833),
+            ),
+            ("urn:adv:crs:ETRS89_UTM32*DE_DHHN2016_NH", Some(25832)),
+            ("urn:adv:crs:DE_DHDN_3GK3*DE_DHHN92_NH", Some(31467)),
+            ("urn:adv:crs:ETRS89", Some(4258)),
+            ("urn:adv:crs:unknown", None),
+            ("local", None),
+        ] {
+            assert_eq!(parse_crs(name), epsg, "{}", name);
+        }
+    }
+
+    #[test]
+    fn cityjson_reference_system_is_read_from_the_metadata() {
+        let cityjson = r#"{
+            "type": "CityJSON",
+            "metadata": { "referenceSystem": "https://www.opengis.net/def/crs/EPSG/0/7415" },
+            "CityObjects": {}
+        }"#;
+
+        assert_eq!(cityjson_reference_system(cityjson), Some(7415));
+        assert_eq!(cityjson_reference_system(r#"{ "type": "CityJSON" }"#), None);
+    }
+}
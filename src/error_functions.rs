@@ -0,0 +1,132 @@
+use crate::write_functions::CityObjectManifest;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::ops::AddAssign;
+use std::path::PathBuf;
+use thiserror::Error;
+
+// Errors that prevent an input file, a city object or an output file from being converted
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not read {path}: {source}")]
+    Read { path: PathBuf, source: io::Error },
+    #[error("could not read archive {path}: {source}")]
+    Archive {
+        path: PathBuf,
+        source: zip::result::ZipError,
+    },
+    #[error("could not read CityGML: {0}")]
+    Citygml(#[from] ecitygml_io::Error),
+    #[error("could not read CityGML: {0}")]
+    CitygmlReader(String),
+    #[error("could not parse XML: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("could not parse JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid CityJSON: {0}")]
+    CityJson(String),
+    #[error("unsupported coordinate reference system: {0}")]
+    Crs(String),
+    #[error("could not import {path}: {source}")]
+    Import { path: PathBuf, source: io::Error },
+    #[error("could not write {path}: {source}")]
+    Write { path: PathBuf, source: io::Error },
+    #[error("{0} has no geometry to write")]
+    NoGeometry(String),
+    #[error("the triangle index list of {0} is not a multiple of 3")]
+    InvalidTriangles(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Wraps an io::Error that occurred while writing the given file
+pub fn write_error(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Error {
+    let path = path.into();
+    move |source| Error::Write { path, source }
+}
+
+// Counts of converted and failed input files, city objects and polygons. The summaries of the
+// single city objects are added up per file and the summaries of the files per run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConversionSummary {
+    pub files_converted: usize,
+    pub files_failed: usize,
+    pub city_objects_converted: usize,
+    // City objects without geometry in any of the requested levels of detail
+    pub city_objects_skipped: usize,
+    // City objects of which at least one output file could not be written
+    pub city_objects_failed: usize,
+    // Polygons that could not be triangulated and are missing in the output
+    pub polygons_failed: usize,
+    // Manifest entries of all converted, skipped and failed city objects
+    #[serde(skip)]
+    pub city_objects: Vec<CityObjectManifest>,
+}
+
+impl ConversionSummary {
+    pub fn file_failed() -> Self {
+        Self {
+            files_failed: 1,
+            ..Default::default()
+        }
+    }
+
+    // True if all files and city objects were converted completely
+    pub fn is_complete(&self) -> bool {
+        self.files_failed == 0 && self.city_objects_failed == 0 && self.polygons_failed == 0
+    }
+
+    // Exit code for batch jobs: 0 if everything was converted, 1 if input files or city objects
+    // failed and 2 if only single polygons could not be triangulated
+    pub fn exit_code(&self) -> u8 {
+        if self.files_failed > 0 || self.city_objects_failed > 0 {
+            1
+        } else if self.polygons_failed > 0 {
+            2
+        } else {
+            0
+        }
+    }
+}
+
+impl AddAssign for ConversionSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.files_converted += other.files_converted;
+        self.files_failed += other.files_failed;
+        self.city_objects_converted += other.city_objects_converted;
+        self.city_objects_skipped += other.city_objects_skipped;
+        self.city_objects_failed += other.city_objects_failed;
+        self.polygons_failed += other.polygons_failed;
+        self.city_objects.extend(other.city_objects);
+    }
+}
+
+impl std::iter::Sum for ConversionSummary {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut total, summary| {
+            total += summary;
+            total
+        })
+    }
+}
+
+impl fmt::Display for ConversionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Files: {} converted, {} failed",
+            self.files_converted, self.files_failed
+        )?;
+        writeln!(
+            f,
+            "City objects: {} converted, {} skipped, {} failed",
+            self.city_objects_converted, self.city_objects_skipped, self.city_objects_failed
+        )?;
+        write!(
+            f,
+            "Polygons failed to triangulate: {}",
+            self.polygons_failed
+        )
+    }
+}
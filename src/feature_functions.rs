@@ -0,0 +1,785 @@
+use crate::conversion_functions::{city_object_class_to_str, level_of_detail_to_number};
+use ecitygml::operations::CityObjectGeometryCollection;
+use ecitygml_core::model::common::{CityObjectClass, LevelOfDetail};
+use egml::model::base::{Gml, Id};
+use egml::model::geometry::{DirectPosition, LinearRing, MultiSurface, Polygon};
+use egml::operations::geometry::Geometry;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::collections::{HashMap, HashSet};
+
+// References to composite surfaces are followed at most this deep, so that cyclic references
+// do not end in an endless loop
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+// A city object nested in a top-level city object together with the gml:id of the feature it
+// belongs to (e.g. the building, building part or installation of a WallSurface)
+#[derive(Debug, Clone)]
+pub struct CityObjectFeature {
+    pub parent_id: String,
+    pub class_name: &'static str,
+    pub geometry: CityObjectGeometryCollection,
+}
+
+// A top-level city object (e.g. a building or a bridge) with all features nested in it. The
+// members include the top-level city object itself with its own geometry (e.g. the lod1Solid
+// of a building).
+#[derive(Debug, Clone)]
+pub struct ParsedCityObject {
+    pub id: Id,
+    pub class: CityObjectClass,
+    pub class_name: &'static str,
+    pub members: Vec<CityObjectFeature>,
+}
+
+// All top-level city objects of a dataset, keyed by their gml:id
+pub type ParsedCityObjects = HashMap<String, ParsedCityObject>;
+
+#[derive(Debug, Clone, Copy)]
+enum GeometryProperty {
+    MultiSurface(LevelOfDetail),
+    Solid(LevelOfDetail),
+    ImplicitRepresentation(LevelOfDetail),
+}
+
+// Placement of the prototype geometry of an ImplicitGeometry: a 4x4 transformation matrix (row
+// by row) applied to the prototype, followed by a translation to the reference point
+#[derive(Debug, Clone)]
+pub(crate) struct ImplicitTransformation {
+    pub(crate) matrix: [f64; 16],
+    pub(crate) reference_point: [f64; 3],
+}
+
+// A surface of a geometry: either a polygon given inline or a reference (xlink:href) to a
+// polygon, composite surface, shell or multi surface given elsewhere in the document
+#[derive(Debug, Clone)]
+enum SurfaceMember {
+    Polygon(Polygon),
+    Reference(String),
+}
+
+// The MultiSurface, Solid or ImplicitGeometry of a feature as it is written in the document
+struct SurfaceGeometry {
+    id: Id,
+    property: GeometryProperty,
+    transformation: Option<ImplicitTransformation>,
+    members: Vec<SurfaceMember>,
+}
+
+// A feature as it is written in the document, before the references are resolved
+struct RawFeature {
+    id: Id,
+    class: CityObjectClass,
+    class_name: &'static str,
+    parent_id: String,
+    geometries: Vec<SurfaceGeometry>,
+}
+
+// All polygons and composite surfaces with a gml:id, used for resolving references
+type SurfaceIndex = HashMap<String, Vec<SurfaceMember>>;
+
+// ecitygml-io only reads buildings, city furniture, roads and solitary vegetation objects.
+// Of a building it only reads the WallSurfaces, RoofSurfaces, GroundSurfaces and
+// BuildingConstructiveElements and attaches the ones of building parts and installations to
+// the building itself. This reads the whole feature hierarchy of every top-level city object
+// again, so that bridges, tunnels, water bodies, building parts, installations,
+// OuterCeilingSurfaces, OuterFloorSurfaces, ClosureSurfaces etc. are converted as well and
+// every feature knows its parent. Elements are matched by their local name, so the same
+// parsing reads CityGML 1.0 and 2.0 files, which ecitygml-io does not read at all.
+//
+// Besides MultiSurfaces, the polygons of Solids, Shells and CompositeSurfaces are read and
+// xlink references are resolved within the document. Polygons of a Solid that are also part of
+// the MultiSurface of a feature (typically the thematic surfaces the solid refers to) are only
+// converted once, with the semantics of that feature.
+pub fn parse_city_objects(xml_document: &str) -> Result<ParsedCityObjects, quick_xml::Error> {
+    let mut raw_city_objects: Vec<(Id, CityObjectClass, &'static str, Vec<RawFeature>)> =
+        Vec::new();
+    let mut surface_index = SurfaceIndex::new();
+
+    let mut reader = Reader::from_str(xml_document);
+    reader.config_mut().trim_text(true);
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let Some((class, class_name)) = top_level_class(e.local_name().as_ref()) else {
+                    continue;
+                };
+
+                let gml_id = extract_gml_id(&e);
+                let xml_snippet = reader.read_text(e.name())?;
+                let id = gml_id.unwrap_or(Id::from_hashed_string(&xml_snippet));
+
+                let mut features = Vec::new();
+                parse_feature(
+                    (class, class_name),
+                    &id,
+                    id.to_string(),
+                    &xml_snippet,
+                    &mut features,
+                    &mut surface_index,
+                )?;
+                raw_city_objects.push((id, class, class_name, features));
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    // Polygons that are part of a MultiSurface, inline or by reference
+    let mut claimed_polygon_ids: HashSet<String> = HashSet::new();
+    let mut resolved_city_objects = Vec::new();
+    for (id, class, class_name, features) in raw_city_objects {
+        let resolved_features: Vec<_> = features
+            .into_iter()
+            .map(|feature| {
+                let geometries: Vec<_> = feature
+                    .geometries
+                    .iter()
+                    .map(|geometry| {
+                        let mut polygons = resolve_surface_members(
+                            &geometry.members,
+                            &surface_index,
+                            &feature.id,
+                            0,
+                        );
+                        if let Some(transformation) = &geometry.transformation {
+                            polygons = transformation.apply(&geometry.id, &polygons);
+                        }
+                        if matches!(geometry.property, GeometryProperty::MultiSurface(_)) {
+                            claimed_polygon_ids
+                                .extend(polygons.iter().map(|p| p.gml.id.to_string()));
+                        }
+                        (geometry.id.clone(), geometry.property, polygons)
+                    })
+                    .collect();
+                (feature, geometries)
+            })
+            .collect();
+        resolved_city_objects.push((id, class, class_name, resolved_features));
+    }
+
+    let mut city_objects = ParsedCityObjects::new();
+    for (id, class, class_name, resolved_features) in resolved_city_objects {
+        let members = resolved_features
+            .into_iter()
+            .map(|(feature, geometries)| {
+                // All polygons of a level of detail are merged into one MultiSurface, which
+                // keeps the gml:id of the first geometry
+                let mut polygons_by_lod: HashMap<LevelOfDetail, (Id, Vec<Polygon>)> =
+                    HashMap::new();
+                for (geometry_id, property, polygons) in geometries {
+                    let is_solid = matches!(property, GeometryProperty::Solid(_));
+                    let polygons = polygons.into_iter().filter(|p| {
+                        !is_solid || !claimed_polygon_ids.contains(&p.gml.id.to_string())
+                    });
+                    polygons_by_lod
+                        .entry(property.lod())
+                        .or_insert_with(|| (geometry_id, Vec::new()))
+                        .1
+                        .extend(polygons);
+                }
+
+                city_object_feature(
+                    feature.id,
+                    (feature.class, feature.class_name),
+                    feature.parent_id,
+                    polygons_by_lod,
+                )
+            })
+            .collect();
+
+        city_objects.insert(
+            id.to_string(),
+            ParsedCityObject {
+                id,
+                class,
+                class_name,
+                members,
+            },
+        );
+    }
+
+    Ok(city_objects)
+}
+
+// Builds a feature with one MultiSurface (gml:id and polygons) per level of detail
+pub(crate) fn city_object_feature(
+    id: Id,
+    (class, class_name): (CityObjectClass, &'static str),
+    parent_id: String,
+    polygons_by_lod: HashMap<LevelOfDetail, (Id, Vec<Polygon>)>,
+) -> CityObjectFeature {
+    let multi_surfaces = polygons_by_lod
+        .into_iter()
+        .filter(|(_, (_, polygons))| !polygons.is_empty())
+        .filter_map(|(lod, (multi_surface_id, polygons))| {
+            MultiSurface::new(Gml::new(multi_surface_id), polygons)
+                .ok()
+                .map(|multi_surface| (lod, multi_surface))
+        })
+        .collect();
+
+    CityObjectFeature {
+        parent_id,
+        class_name,
+        geometry: CityObjectGeometryCollection {
+            gml: Gml::new(id),
+            class,
+            implicit_geometries: HashMap::new(),
+            multi_surfaces,
+            solids: HashMap::new(),
+        },
+    }
+}
+
+// Reads the geometries of a feature and adds it and all features nested in it to `features`
+fn parse_feature(
+    (class, class_name): (CityObjectClass, &'static str),
+    id: &Id,
+    parent_id: String,
+    xml_document: &str,
+    features: &mut Vec<RawFeature>,
+    surface_index: &mut SurfaceIndex,
+) -> Result<(), quick_xml::Error> {
+    let mut geometries = Vec::new();
+
+    let mut reader = Reader::from_str(xml_document);
+    reader.config_mut().trim_text(true);
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let local_name = e.local_name();
+
+                if let Some(nested_class) = feature_class(local_name.as_ref()) {
+                    let gml_id = extract_gml_id(&e);
+                    let xml_snippet = reader.read_text(e.name())?;
+                    let nested_id = gml_id.unwrap_or(Id::from_hashed_string(&xml_snippet));
+
+                    parse_feature(
+                        nested_class,
+                        &nested_id,
+                        id.to_string(),
+                        &xml_snippet,
+                        features,
+                        surface_index,
+                    )?;
+                } else if let Some(property) = geometry_property(local_name.as_ref()) {
+                    let xml_snippet = reader.read_text(e.name())?;
+                    let (geometry_id, transformation, members) = match property {
+                        GeometryProperty::ImplicitRepresentation(lod) => {
+                            let (geometry_id, transformation, members) =
+                                parse_implicit_geometry(&xml_snippet, surface_index)?;
+                            // The id prefixes the polygon ids of the instance, so it is derived
+                            // from the feature if the ImplicitGeometry has none
+                            let geometry_id = geometry_id.or_else(|| {
+                                let lod = level_of_detail_to_number(lod);
+                                Id::try_from(format!("{}_implicit_lod{}", id, lod)).ok()
+                            });
+                            (geometry_id, Some(transformation), members)
+                        }
+                        _ => {
+                            let (geometry_id, members) =
+                                parse_surface_members(&xml_snippet, surface_index)?;
+                            (geometry_id, None, members)
+                        }
+                    };
+                    geometries.push(SurfaceGeometry {
+                        id: geometry_id.unwrap_or(Id::from_hashed_string(&xml_snippet)),
+                        property,
+                        transformation,
+                        members,
+                    });
+                } else if local_name
+                    .as_ref()
+                    .first()
+                    .is_some_and(u8::is_ascii_uppercase)
+                {
+                    // Objects of other types (e.g. rooms or appearances) are skipped with
+                    // everything they contain, so their geometry is not taken for this feature
+                    reader.read_to_end(e.name())?;
+                }
+            }
+            // The whole geometry may be given by reference, e.g. <lod2Solid xlink:href="#..."/>
+            Event::Empty(e) => {
+                if let Some(property) = geometry_property(e.local_name().as_ref())
+                    && !matches!(property, GeometryProperty::ImplicitRepresentation(_))
+                    && let Some(reference) = extract_reference(&e)
+                {
+                    geometries.push(SurfaceGeometry {
+                        id: Id::from_hashed_string(&reference),
+                        property,
+                        transformation: None,
+                        members: vec![SurfaceMember::Reference(reference)],
+                    });
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    features.push(RawFeature {
+        id: id.clone(),
+        class,
+        class_name,
+        parent_id,
+        geometries,
+    });
+    Ok(())
+}
+
+impl GeometryProperty {
+    fn lod(&self) -> LevelOfDetail {
+        match self {
+            GeometryProperty::MultiSurface(lod)
+            | GeometryProperty::Solid(lod)
+            | GeometryProperty::ImplicitRepresentation(lod) => *lod,
+        }
+    }
+}
+
+impl ImplicitTransformation {
+    // Places the polygons of the prototype. Every instance gets its own polygon gml:ids
+    // (<implicit geometry id>_<prototype polygon id>), as a prototype is usually shared by many
+    // city objects.
+    fn apply(&self, implicit_geometry_id: &Id, polygons: &[Polygon]) -> Vec<Polygon> {
+        polygons
+            .iter()
+            .filter_map(|polygon| {
+                let id =
+                    Id::try_from(format!("{}_{}", implicit_geometry_id, polygon.gml.id)).ok()?;
+                let exterior = self.apply_to_ring(&id, 0, &polygon.exterior)?;
+                let interior = polygon
+                    .interior
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, ring)| self.apply_to_ring(&id, index + 1, ring))
+                    .collect();
+                Polygon::new(Gml::new(id), exterior, interior).ok()
+            })
+            .collect()
+    }
+
+    pub(crate) fn transform_point(&self, [x, y, z]: [f64; 3]) -> [f64; 3] {
+        let m = &self.matrix;
+        let [rx, ry, rz] = self.reference_point;
+        [
+            m[0] * x + m[1] * y + m[2] * z + m[3] + rx,
+            m[4] * x + m[5] * y + m[6] * z + m[7] + ry,
+            m[8] * x + m[9] * y + m[10] * z + m[11] + rz,
+        ]
+    }
+
+    fn apply_to_ring(
+        &self,
+        polygon_id: &Id,
+        index: usize,
+        ring: &LinearRing,
+    ) -> Option<LinearRing> {
+        let points = ring
+            .points()
+            .into_iter()
+            .map(|p| {
+                let [x, y, z] = self.transform_point(p.coords());
+                DirectPosition::new(x, y, z).ok()
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let ring_id = Id::from_hashed_string(&format!("{}_{}", polygon_id, index));
+        match linear_ring(ring_id, points) {
+            Ok(ring) => Some(ring),
+            Err(e) => {
+                eprintln!("Polygon {} contains an invalid ring: {}", polygon_id, e);
+                None
+            }
+        }
+    }
+}
+
+// Reads an ImplicitGeometry: its transformation matrix, reference point and the prototype
+// geometry, which is either given inline or by reference (relativeGeometry xlink:href)
+fn parse_implicit_geometry(
+    xml_document: &str,
+    surface_index: &mut SurfaceIndex,
+) -> Result<(Option<Id>, ImplicitTransformation, Vec<SurfaceMember>), quick_xml::Error> {
+    let mut geometry_id = None;
+    let mut transformation = ImplicitTransformation {
+        matrix: [
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ],
+        reference_point: [0.0; 3],
+    };
+    let mut members = Vec::new();
+
+    let mut reader = Reader::from_str(xml_document);
+    reader.config_mut().trim_text(true);
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"ImplicitGeometry" => geometry_id = extract_gml_id(&e),
+                b"transformationMatrix" => {
+                    let text = reader.read_text(e.name())?;
+                    let values: Option<Vec<f64>> =
+                        text.split_whitespace().map(|v| v.parse().ok()).collect();
+                    match values.and_then(|values| <[f64; 16]>::try_from(values).ok()) {
+                        Some(matrix) => transformation.matrix = matrix,
+                        None => eprintln!(
+                            "ImplicitGeometry {} has an invalid transformation matrix, the identity is used.",
+                            geometry_id.as_ref().map(Id::to_string).unwrap_or_default()
+                        ),
+                    }
+                }
+                b"relativeGeometry" | b"relativeGMLGeometry" => {
+                    if let Some(reference) = extract_reference(&e) {
+                        members.push(SurfaceMember::Reference(reference));
+                        reader.read_to_end(e.name())?;
+                    } else {
+                        let xml_snippet = reader.read_text(e.name())?;
+                        let (_, prototype_members) =
+                            parse_surface_members(&xml_snippet, surface_index)?;
+                        members.extend(prototype_members);
+                    }
+                }
+                // The prototype is read completely above, so this is the reference point
+                b"pos" => {
+                    let text = reader.read_text(e.name())?;
+                    if let Some(point) = parse_positions(&text).and_then(|p| p.first().copied()) {
+                        transformation.reference_point = point.coords();
+                    }
+                }
+                _ => (),
+            },
+            Event::Empty(e) => {
+                if matches!(
+                    e.local_name().as_ref(),
+                    b"relativeGeometry" | b"relativeGMLGeometry"
+                ) && let Some(reference) = extract_reference(&e)
+                {
+                    members.push(SurfaceMember::Reference(reference));
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    Ok((geometry_id, transformation, members))
+}
+
+// Reads all polygons of a MultiSurface, CompositeSurface, Solid, Shell, OrientableSurface etc.
+// at any depth, together with the references to surfaces given elsewhere. Polygons and
+// composite surfaces with a gml:id are added to the index. Returns the gml:id of the outermost
+// geometry as well.
+fn parse_surface_members(
+    xml_document: &str,
+    surface_index: &mut SurfaceIndex,
+) -> Result<(Option<Id>, Vec<SurfaceMember>), quick_xml::Error> {
+    let mut geometry_id = None;
+    let mut is_first_element = true;
+    let mut members = Vec::new();
+
+    let mut reader = Reader::from_str(xml_document);
+    reader.config_mut().trim_text(true);
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let gml_id = extract_gml_id(&e);
+                if is_first_element {
+                    geometry_id = gml_id.clone();
+                    is_first_element = false;
+                }
+
+                if let Some(reference) = extract_reference(&e) {
+                    members.push(SurfaceMember::Reference(reference));
+                    reader.read_to_end(e.name())?;
+                    continue;
+                }
+
+                match e.local_name().as_ref() {
+                    b"Polygon" => {
+                        let xml_snippet = reader.read_text(e.name())?;
+                        let id = gml_id.unwrap_or(Id::from_hashed_string(&xml_snippet));
+                        if let Some(polygon) = parse_polygon(&id, &xml_snippet)? {
+                            surface_index.insert(
+                                id.to_string(),
+                                vec![SurfaceMember::Polygon(polygon.clone())],
+                            );
+                            members.push(SurfaceMember::Polygon(polygon));
+                        }
+                    }
+                    b"CompositeSurface" | b"Shell" | b"MultiSurface" | b"Solid" => {
+                        // Only composite surfaces with a gml:id can be referenced
+                        if let Some(id) = gml_id {
+                            let xml_snippet = reader.read_text(e.name())?;
+                            let (_, nested_members) =
+                                parse_surface_members(&xml_snippet, surface_index)?;
+                            surface_index.insert(id.to_string(), nested_members.clone());
+                            members.extend(nested_members);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Event::Empty(e) => {
+                is_first_element = false;
+                if let Some(reference) = extract_reference(&e) {
+                    members.push(SurfaceMember::Reference(reference));
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    Ok((geometry_id, members))
+}
+
+// Reads the exterior and interior rings of a polygon, given as gml:posList, gml:coordinates or
+// a sequence of gml:pos. Invalid polygons are reported and skipped.
+fn parse_polygon(id: &Id, xml_document: &str) -> Result<Option<Polygon>, quick_xml::Error> {
+    // Points of every ring and whether it is the exterior one
+    let mut rings: Vec<(bool, Vec<DirectPosition>)> = Vec::new();
+    let mut is_exterior = true;
+    let mut is_valid = true;
+
+    let mut reader = Reader::from_str(xml_document);
+    reader.config_mut().trim_text(true);
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"exterior" => is_exterior = true,
+                b"interior" => is_exterior = false,
+                b"LinearRing" => rings.push((is_exterior, Vec::new())),
+                b"posList" | b"pos" | b"coordinates" => {
+                    // gml:coordinates (GML 3.1.1) separates the coordinates of a tuple by commas
+                    let text = reader.read_text(e.name())?.replace(',', " ");
+                    match parse_positions(&text) {
+                        Some(points) => {
+                            if let Some((_, ring_points)) = rings.last_mut() {
+                                ring_points.extend(points);
+                            }
+                        }
+                        None => is_valid = false,
+                    }
+                }
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+
+    if !is_valid {
+        eprintln!("Polygon {} contains invalid coordinates.", id);
+        return Ok(None);
+    }
+
+    let mut exterior = None;
+    let mut interior = Vec::new();
+    for (index, (is_exterior, points)) in rings.into_iter().enumerate() {
+        let ring_id = Id::from_hashed_string(&format!("{}_{}", id, index));
+        match linear_ring(ring_id, points) {
+            Ok(ring) if is_exterior && exterior.is_none() => exterior = Some(ring),
+            Ok(ring) => interior.push(ring),
+            Err(e) => {
+                eprintln!("Polygon {} contains an invalid ring: {}", id, e);
+                if is_exterior {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    let Some(exterior) = exterior else {
+        eprintln!("Polygon {} has no exterior ring.", id);
+        return Ok(None);
+    };
+    match Polygon::new(Gml::new(id.clone()), exterior, interior) {
+        Ok(polygon) => Ok(Some(polygon)),
+        Err(e) => {
+            eprintln!("Polygon {} contains invalid geometry: {}", id, e);
+            Ok(None)
+        }
+    }
+}
+
+// Removes repeated points and the closing point, which egml does not allow in a ring
+pub(crate) fn linear_ring(
+    id: Id,
+    mut points: Vec<DirectPosition>,
+) -> Result<LinearRing, egml::Error> {
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    LinearRing::new(Gml::new(id), points)
+}
+
+fn parse_positions(text: &str) -> Option<Vec<DirectPosition>> {
+    let values: Vec<f64> = text
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Option<_>>()?;
+    if !values.len().is_multiple_of(3) {
+        return None;
+    }
+    values
+        .chunks_exact(3)
+        .map(|c| DirectPosition::new(c[0], c[1], c[2]).ok())
+        .collect()
+}
+
+// Replaces the references by the polygons they point to
+fn resolve_surface_members(
+    members: &[SurfaceMember],
+    surface_index: &SurfaceIndex,
+    feature_id: &Id,
+    depth: usize,
+) -> Vec<Polygon> {
+    let mut polygons = Vec::new();
+    for member in members {
+        match member {
+            SurfaceMember::Polygon(polygon) => polygons.push(polygon.clone()),
+            SurfaceMember::Reference(reference) => match surface_index.get(reference) {
+                Some(referenced_members) if depth < MAX_REFERENCE_DEPTH => {
+                    polygons.extend(resolve_surface_members(
+                        referenced_members,
+                        surface_index,
+                        feature_id,
+                        depth + 1,
+                    ));
+                }
+                _ => eprintln!(
+                    "Feature {} refers to surface {} which could not be resolved.",
+                    feature_id, reference
+                ),
+            },
+        }
+    }
+    polygons
+}
+
+pub(crate) fn top_level_class(local_name: &[u8]) -> Option<(CityObjectClass, &'static str)> {
+    let class = match local_name {
+        b"Building" => CityObjectClass::Building,
+        b"Bridge" => CityObjectClass::Bridge,
+        b"Tunnel" => CityObjectClass::Tunnel,
+        b"CityFurniture" => CityObjectClass::CityFurniture,
+        b"Road" => CityObjectClass::Road,
+        b"Railway" => CityObjectClass::Railway,
+        b"Track" => CityObjectClass::Track,
+        b"Square" => CityObjectClass::Square,
+        b"Waterway" => CityObjectClass::Waterway,
+        b"WaterBody" => CityObjectClass::WaterBody,
+        b"PlantCover" => CityObjectClass::PlantCover,
+        b"SolitaryVegetationObject" => CityObjectClass::SolitaryVegetationObject,
+        b"GenericOccupiedSpace" => CityObjectClass::GenericOccupiedSpace,
+        b"GenericUnoccupiedSpace" => CityObjectClass::GenericUnoccupiedSpace,
+        b"OtherConstruction" => CityObjectClass::OtherConstruction,
+        // CityGML 1.0 and 2.0
+        b"TransportationComplex" => {
+            return Some((CityObjectClass::Road, "TransportationComplex"));
+        }
+        b"GenericCityObject" => {
+            return Some((CityObjectClass::GenericOccupiedSpace, "GenericCityObject"));
+        }
+        _ => return None,
+    };
+    Some((class, city_object_class_to_str(class)))
+}
+
+pub(crate) fn feature_class(local_name: &[u8]) -> Option<(CityObjectClass, &'static str)> {
+    let class = match local_name {
+        b"BuildingPart" => CityObjectClass::BuildingPart,
+        b"BuildingInstallation" => CityObjectClass::BuildingInstallation,
+        b"BuildingConstructiveElement" => CityObjectClass::BuildingConstructiveElement,
+        b"BridgePart" => CityObjectClass::BridgePart,
+        b"BridgeInstallation" => CityObjectClass::BridgeInstallation,
+        b"BridgeConstructiveElement" => CityObjectClass::BridgeConstructiveElement,
+        b"TunnelPart" => CityObjectClass::TunnelPart,
+        b"TunnelInstallation" => CityObjectClass::TunnelInstallation,
+        b"TunnelConstructiveElement" => CityObjectClass::TunnelConstructiveElement,
+        b"WallSurface" => CityObjectClass::WallSurface,
+        b"RoofSurface" => CityObjectClass::RoofSurface,
+        b"GroundSurface" => CityObjectClass::GroundSurface,
+        b"OuterCeilingSurface" => CityObjectClass::OuterCeilingSurface,
+        b"OuterFloorSurface" => CityObjectClass::OuterFloorSurface,
+        // CityObjectClass has no ClosureSurface, so only its class name is kept
+        b"ClosureSurface" => {
+            return Some((CityObjectClass::GenericThematicSurface, "ClosureSurface"));
+        }
+        b"WindowSurface" => CityObjectClass::WindowSurface,
+        b"DoorSurface" => CityObjectClass::DoorSurface,
+        b"Window" => CityObjectClass::Window,
+        b"Door" => CityObjectClass::Door,
+        b"Section" => CityObjectClass::Section,
+        b"Intersection" => CityObjectClass::Intersection,
+        b"TrafficSpace" => CityObjectClass::TrafficSpace,
+        b"AuxiliaryTrafficSpace" => CityObjectClass::AuxiliaryTrafficSpace,
+        b"TrafficArea" => CityObjectClass::TrafficArea,
+        b"AuxiliaryTrafficArea" => CityObjectClass::AuxiliaryTrafficArea,
+        b"Marking" => CityObjectClass::Marking,
+        b"Hole" => CityObjectClass::Hole,
+        b"HoleSurface" => CityObjectClass::HoleSurface,
+        b"WaterSurface" => CityObjectClass::WaterSurface,
+        b"WaterGroundSurface" => CityObjectClass::WaterGroundSurface,
+        b"GenericThematicSurface" => CityObjectClass::GenericThematicSurface,
+        // CityGML 1.0 and 2.0
+        b"BridgeConstructionElement" => CityObjectClass::BridgeConstructiveElement,
+        b"WaterClosureSurface" => {
+            return Some((
+                CityObjectClass::GenericThematicSurface,
+                "WaterClosureSurface",
+            ));
+        }
+        _ => return None,
+    };
+    Some((class, city_object_class_to_str(class)))
+}
+
+// Geometry properties of CityGML 3.0 (lod0MultiSurface, lod2Solid, ...) and of CityGML 1.0 and
+// 2.0 (additionally lod0FootPrint, lod0RoofEdge, lod2Geometry, lod1MultiSolid, ...). LOD4 of
+// CityGML 1.0 and 2.0 is converted as LOD3, as citygml-tools does when upgrading to 3.0.
+fn geometry_property(local_name: &[u8]) -> Option<GeometryProperty> {
+    if matches!(local_name, b"lod0FootPrint" | b"lod0RoofEdge") {
+        return Some(GeometryProperty::MultiSurface(LevelOfDetail::Zero));
+    }
+
+    let (lod, property) = local_name.strip_prefix(b"lod")?.split_first()?;
+    let lod = match lod {
+        b'0' => LevelOfDetail::Zero,
+        b'1' => LevelOfDetail::One,
+        b'2' => LevelOfDetail::Two,
+        b'3' | b'4' => LevelOfDetail::Three,
+        _ => return None,
+    };
+    match property {
+        // The boundary surfaces of CityGML 2.0 water bodies have a single lodXSurface
+        b"MultiSurface" | b"Surface" | b"Geometry" => Some(GeometryProperty::MultiSurface(lod)),
+        b"Solid" | b"MultiSolid" => Some(GeometryProperty::Solid(lod)),
+        b"ImplicitRepresentation" => Some(GeometryProperty::ImplicitRepresentation(lod)),
+        _ => None,
+    }
+}
+
+fn extract_gml_id(e: &BytesStart) -> Option<Id> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == b"id")
+        .and_then(|a| a.unescape_value().ok())
+        .and_then(|value| Id::try_from(value.as_ref()).ok())
+}
+
+// The gml:id an xlink:href attribute refers to, without the leading '#'
+fn extract_reference(e: &BytesStart) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == b"href")
+        .and_then(|a| a.unescape_value().ok())
+        .map(|value| value.trim_start_matches('#').to_string())
+}
@@ -1,175 +1,1188 @@
-use crate::translation_module::process_building_components_sequential;
+use crate::feature_functions::linear_ring;
+use crate::write_functions::ImportedBoundingBoxes;
 use earcut::Earcut;
-use earcut::utils3d::project3d_to_2d;
-use ecitygml_core::model::building::Building;
-use ecitygml_core::operations::FeatureWithGeometry;
-use egml::model::geometry::Polygon;
+use ecitygml::operations::CityObjectGeometryCollection;
+use ecitygml_core::model::common::LevelOfDetail;
+use egml::model::geometry::{DirectPosition, Envelope, LinearRing, MultiSurface, Polygon};
 use egml::operations::geometry::Geometry;
+use spade::{
+    AngleLimit, ConstrainedDelaunayTriangulation, Point2, RefinementParameters, Triangulation,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
 
-// This function is used to calculate the translation parameters for a single building
-pub fn get_building_wise_translation_parameters(
-    input_building: &Building,
-) -> Option<(f64, f64, f64)> {
-    let all_building_points = process_building_components_sequential(input_building);
+// Vertices and triangles of the corner reflectors that indicate a bounding box
+pub type BoundingBoxGeometry = (Vec<[f64; 3]>, Vec<[u64; 3]>);
 
-    if all_building_points.is_empty() {
-        return None;
+// Point of a city object that becomes the origin of its local coordinates with --tbw. A fixed
+// origin is the same for all city objects, so they stay aligned to each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LocalOrigin {
+    #[default]
+    EnvelopeCenter,
+    EnvelopeLower,
+    VertexCentroid,
+    // Centroid of the ground surfaces, or the bottom centre of the envelope without them
+    GroundCentroid,
+    Fixed([f64; 3]),
+}
+
+impl FromStr for LocalOrigin {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "envelope-center" => Ok(LocalOrigin::EnvelopeCenter),
+            "envelope-lower" => Ok(LocalOrigin::EnvelopeLower),
+            "vertex-centroid" => Ok(LocalOrigin::VertexCentroid),
+            "ground-centroid" => Ok(LocalOrigin::GroundCentroid),
+            _ => {
+                let coordinates = name
+                    .strip_prefix("fixed:")
+                    .ok_or_else(|| format!("unknown origin {}", name))?;
+                let coordinates: Vec<f64> = coordinates
+                    .split(',')
+                    .map(|c| c.trim().parse::<f64>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| format!("invalid fixed origin {}: {}", name, e))?;
+                match coordinates[..] {
+                    [x, y, z] => Ok(LocalOrigin::Fixed([x, y, z])),
+                    _ => Err(format!("the fixed origin {} needs x,y,z", name)),
+                }
+            }
+        }
+    }
+}
+
+// Axis that points up in the written coordinates. CityGML and CityJSON are Z-up, game engines
+// and glTF are usually Y-up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpAxis {
+    Y,
+    #[default]
+    Z,
+}
+
+impl FromStr for UpAxis {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "y" => Ok(UpAxis::Y),
+            "z" => Ok(UpAxis::Z),
+            _ => Err(format!("unknown up axis {}, expected y or z", name)),
+        }
+    }
+}
+
+// Transform of the (translated) coordinates into the written ones: they are scaled, rotated so
+// that the up axis points up and then shifted by the offset, given in the written units and axes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputTransform {
+    pub up_axis: UpAxis,
+    pub scale: f64,
+    pub offset: [f64; 3],
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        Self {
+            up_axis: UpAxis::Z,
+            scale: 1.0,
+            offset: [0.0; 3],
+        }
+    }
+}
+
+impl OutputTransform {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, point: [f64; 3]) -> [f64; 3] {
+        let [x, y, z] = self.rotate(point.map(|c| c * self.scale));
+        [x + self.offset[0], y + self.offset[1], z + self.offset[2]]
+    }
+
+    // Y-up is a rotation about the x axis, so the winding of the triangles is kept
+    fn rotate(&self, [x, y, z]: [f64; 3]) -> [f64; 3] {
+        match self.up_axis {
+            UpAxis::Y => [x, z, -y],
+            UpAxis::Z => [x, y, z],
+        }
+    }
+
+    // Row-major 4x4 matrix that maps the coordinates in the CRS of the output (before the
+    // translation into a local CRS) onto the written coordinates
+    pub fn matrix(&self, translation: [f64; 3]) -> [[f64; 4]; 4] {
+        let columns = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+            .map(|axis| self.rotate(axis).map(|c| c * self.scale));
+        let shift = self.apply(translation);
+        let row = |i: usize| [columns[0][i], columns[1][i], columns[2][i], shift[i]];
+        [row(0), row(1), row(2), [0.0, 0.0, 0.0, 1.0]]
     }
+}
 
-    let mut sum_x = 0.0;
-    let mut sum_y = 0.0;
-    let mut sum_z = 0.0;
+pub fn envelope_center(envelope: &Envelope) -> [f64; 3] {
+    let lower = envelope.lower_corner();
+    let upper = envelope.upper_corner();
+    [
+        (lower.x() + upper.x()) / 2.0,
+        (lower.y() + upper.y()) / 2.0,
+        (lower.z() + upper.z()) / 2.0,
+    ]
+}
 
-    for point in &all_building_points {
-        sum_x += point[0];
-        sum_y += point[1];
-        sum_z += point[2];
+// This function is used to calculate the mean of all vertices of the given multi surfaces
+pub fn vertex_centroid<'a>(
+    multi_surfaces: impl Iterator<Item = &'a MultiSurface>,
+) -> Option<[f64; 3]> {
+    let mut sum = [0.0; 3];
+    let mut count = 0;
+    let points = multi_surfaces
+        .flat_map(|multi_surface| multi_surface.surface_member())
+        .flat_map(|polygon| std::iter::once(&polygon.exterior).chain(&polygon.interior))
+        .flat_map(|ring| ring.points());
+    for point in points {
+        sum[0] += point.x();
+        sum[1] += point.y();
+        sum[2] += point.z();
+        count += 1;
     }
 
-    let count = all_building_points.len() as f64;
-    Some((sum_x / count, sum_y / count, sum_z / count))
+    (count > 0).then(|| sum.map(|s| s / count as f64))
+}
+
+// Algorithm the polygons are triangulated with
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Triangulator {
+    #[default]
+    Earcut,
+    // Constrained Delaunay triangulation of the rings, optionally refined with Steiner points
+    // until no angle is below min_angle (in degrees) and no triangle is larger than an
+    // equilateral one with max_edge_length
+    Cdt {
+        min_angle: Option<f64>,
+        max_edge_length: Option<f64>,
+    },
 }
 
-pub fn triangulate(input_polygon: &Polygon) -> (Vec<u32>, Vec<[f64; 3]>) {
-    // Collect all 3D points
-    let mut all_points: Vec<[f64; 3]> = Vec::new();
+impl FromStr for Triangulator {
+    type Err = String;
 
-    // Exterior ring
-    let exterior_ring = &input_polygon.exterior;
-    for point in exterior_ring.points() {
-        all_points.push([point.x(), point.y(), point.z()]);
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "earcut" => Ok(Triangulator::Earcut),
+            "cdt" => Ok(Triangulator::Cdt {
+                min_angle: None,
+                max_edge_length: None,
+            }),
+            _ => Err(format!(
+                "unknown triangulator {}, expected earcut or cdt",
+                name
+            )),
+        }
     }
+}
+
+// Normals written with the vertices of the OBJ files: the normal of the polygon at all of its
+// vertices (flat shading) or, at every vertex, the area-weighted mean of the normals of the
+// polygons of the thematic surface that meet there (smooth shading)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normals {
+    Flat,
+    Smooth,
+}
 
-    // Interior rings
-    for interior_ring in &input_polygon.interior {
-        for point in interior_ring.points() {
-            all_points.push([point.x(), point.y(), point.z()]);
+impl FromStr for Normals {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "flat" => Ok(Normals::Flat),
+            "smooth" => Ok(Normals::Smooth),
+            _ => Err(format!("unknown normals {}, expected flat or smooth", name)),
         }
     }
+}
+
+// Area-weighted, not yet normalised normal of a triangulated polygon, so the normals of several
+// polygons can be added up independently of how they were triangulated. The triangles are
+// wound like the exterior ring, which faces outwards in CityGML, so the normal points outwards
+// as well.
+pub fn polygon_normal(triangles: &[u32], points: &[[f64; 3]]) -> [f64; 3] {
+    triangles
+        .chunks_exact(3)
+        .map(|t| {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|i| points[i as usize]);
+            cross(sub(b, a), sub(c, a))
+        })
+        .fold([0.0; 3], add)
+}
+
+// Unit normal of an area-weighted normal; zero for vertices without triangles
+pub fn unit_normal(normal: [f64; 3]) -> [f64; 3] {
+    let length = norm(normal);
+    if length > 0.0 {
+        normal.map(|c| c / length)
+    } else {
+        [0.0; 3]
+    }
+}
 
-    // Project to 2D
-    let mut all_points_projected: Vec<[f64; 2]> = Vec::new();
-    let _ = project3d_to_2d(
-        &all_points,
-        exterior_ring.points().len(),
-        &mut all_points_projected,
-    );
+// Upper limit of the Steiner points added to a single polygon by the refinement, which may not
+// terminate for large minimum angles
+const MAX_STEINER_POINTS: usize = 10_000;
+
+// Largest minimum angle in degrees the refinement reliably reaches; above it, it only stops at
+// MAX_STEINER_POINTS. `triangulate` clamps larger minimum angles to it.
+pub const MAX_MIN_ANGLE: f64 = 30.0;
+
+// Triangulates a polygon with its holes. The rings are projected onto the plane of the polygon,
+// given by its Newell normal, so nearly vertical, slightly non-planar and collinear-starting
+// rings are triangulated as well. The triangles are wound like the exterior ring; degenerate
+// ones are dropped, so an empty triangle list means the polygon could not be triangulated.
+// Steiner points of a refined constrained Delaunay triangulation are appended to the points.
+pub fn triangulate(
+    input_polygon: &Polygon,
+    triangulator: Triangulator,
+) -> (Vec<u32>, Vec<[f64; 3]>) {
+    // Collect all 3D points without repeated (e.g. closing) points
+    let exterior = ring_coordinates(&input_polygon.exterior);
+    let interiors: Vec<Vec<[f64; 3]>> = input_polygon
+        .interior
+        .iter()
+        .map(ring_coordinates)
+        .filter(|ring| ring.len() >= 3)
+        .collect();
+    if exterior.len() < 3 {
+        return (Vec::new(), Vec::new());
+    }
 
     // Build hole indices (start index of each hole ring in the flattened point list)
+    let mut all_points = exterior.clone();
     let mut hole_indices: Vec<u32> = Vec::new();
-    if !input_polygon.interior.is_empty() {
-        let mut offset = exterior_ring.points().len() as u32;
-        for ring in &input_polygon.interior {
-            hole_indices.push(offset);
-            offset += ring.points().len() as u32;
-        }
+    for ring in &interiors {
+        hole_indices.push(all_points.len() as u32);
+        all_points.extend(ring);
     }
 
+    // Project to 2D, relative to the first point to keep large projected coordinates precise
+    let normal = newell_normal(&exterior);
+    let origin = all_points[0];
+    let mut relative_points: Vec<[f64; 3]> = all_points.iter().map(|p| sub(*p, origin)).collect();
+    let plane = plane_basis(&relative_points, normal);
+    let all_points_projected = project_to_plane(&relative_points, plane);
+
     // Perform triangulation
-    let mut triangles = vec![];
-    let mut earcut = Earcut::new();
-    earcut.earcut(
-        all_points_projected.iter().copied(),
-        &hole_indices,
-        &mut triangles,
-    );
+    let mut triangles: Vec<u32> = vec![];
+    match triangulator {
+        Triangulator::Earcut => {
+            let mut earcut = Earcut::new();
+            earcut.earcut(
+                all_points_projected.iter().copied(),
+                &hole_indices,
+                &mut triangles,
+            );
+        }
+        Triangulator::Cdt {
+            min_angle,
+            max_edge_length,
+        } => {
+            // Without a plane the Steiner points could not be placed in 3D
+            let refinement = plane
+                .filter(|_| min_angle.is_some() || max_edge_length.is_some())
+                .map(|_| {
+                    let mut parameters = RefinementParameters::<f64>::new()
+                        .exclude_outer_faces(true)
+                        .with_max_additional_vertices(MAX_STEINER_POINTS)
+                        .with_angle_limit(AngleLimit::from_deg(
+                            min_angle.unwrap_or(0.0).min(MAX_MIN_ANGLE),
+                        ));
+                    if let Some(length) = max_edge_length {
+                        parameters =
+                            parameters.with_max_allowed_area(3f64.sqrt() / 4.0 * length * length);
+                    }
+                    parameters
+                });
+            let ring_starts: Vec<usize> = std::iter::once(0)
+                .chain(hole_indices.iter().map(|i| *i as usize))
+                .collect();
+            let steiner_points;
+            (triangles, steiner_points) =
+                constrained_delaunay(&all_points_projected, &ring_starts, refinement);
+
+            // Steiner points that split a ring edge are interpolated along the edge in 3D, so
+            // they lie on the edges of neighbouring polygons as well; the others are placed on
+            // the mean plane of the polygon
+            if let Some([u, v, n]) = plane {
+                let height = relative_points.iter().map(|p| dot(*p, n)).sum::<f64>()
+                    / relative_points.len() as f64;
+                for steiner_point in steiner_points {
+                    let point = match ring_edge_position(
+                        steiner_point,
+                        &all_points_projected,
+                        &ring_starts,
+                    ) {
+                        Some((a, b, t)) => {
+                            let [a, b] = [relative_points[a], relative_points[b]];
+                            [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+                        }
+                        None => {
+                            let [x, y] = steiner_point;
+                            [0, 1, 2].map(|i| u[i] * x + v[i] * y + n[i] * height)
+                        }
+                    };
+                    relative_points.push(point);
+                    all_points.push([0, 1, 2].map(|i| origin[i] + point[i]));
+                }
+            }
+        }
+    }
 
+    let triangles = orient_triangles(&triangles, &relative_points, normal);
     (triangles, all_points)
 }
 
-pub fn construct_buffered_bounding_box(
-    input_building: &Building,
-) -> (Vec<[f64; 3]>, Vec<[u64; 3]>) {
-    let mut vertices = Vec::new();
-    let mut indices = Vec::new();
+// Constrained Delaunay triangulation of the projected rings, which start at the given indices.
+// Returns the triangles inside the exterior ring and outside of the holes, and the Steiner
+// points the refinement added (indexed after the given points).
+fn constrained_delaunay(
+    points: &[[f64; 2]],
+    ring_starts: &[usize],
+    refinement: Option<RefinementParameters<f64>>,
+) -> (Vec<u32>, Vec<[f64; 2]>) {
+    let mut cdt = ConstrainedDelaunayTriangulation::<Point2<f64>>::new();
+    let mut handles = Vec::with_capacity(points.len());
+    for [x, y] in points {
+        match cdt.insert(Point2::new(*x, *y)) {
+            Ok(handle) => handles.push(handle),
+            Err(_) => return (Vec::new(), Vec::new()),
+        }
+    }
+
+    // Edges that would cross others (self-intersecting rings) are left out
+    let ring_ends = ring_starts.iter().skip(1).copied().chain([points.len()]);
+    let rings: Vec<(usize, usize)> = ring_starts.iter().copied().zip(ring_ends).collect();
+    for (start, end) in &rings {
+        for i in *start..*end {
+            let next = if i + 1 == *end { *start } else { i + 1 };
+            if handles[i] != handles[next] {
+                cdt.try_add_constraint(handles[i], handles[next]);
+            }
+        }
+    }
+
+    if let Some(parameters) = refinement {
+        cdt.refine(parameters);
+    }
+
+    // Points merged by the triangulation keep the index of their first occurrence
+    let mut indices: HashMap<usize, u32> = HashMap::new();
+    for (i, handle) in handles.iter().enumerate() {
+        indices.entry(handle.index()).or_insert(i as u32);
+    }
+
+    let ring_slices: Vec<&[[f64; 2]]> = rings
+        .iter()
+        .map(|(start, end)| &points[*start..*end])
+        .collect();
+    let mut triangles = Vec::new();
+    let mut steiner_points = Vec::new();
+    for face in cdt.inner_faces() {
+        let positions = face.positions().map(|p| [p.x, p.y]);
+        let centre = [
+            (positions[0][0] + positions[1][0] + positions[2][0]) / 3.0,
+            (positions[0][1] + positions[1][1] + positions[2][1]) / 3.0,
+        ];
+        if !inside_rings(centre, ring_slices.iter().copied()) {
+            continue;
+        }
+        for vertex in face.vertices() {
+            let index = *indices.entry(vertex.fix().index()).or_insert_with(|| {
+                steiner_points.push([vertex.position().x, vertex.position().y]);
+                (points.len() + steiner_points.len() - 1) as u32
+            });
+            triangles.push(index);
+        }
+    }
+    (triangles, steiner_points)
+}
+
+// The ring edge a projected point lies on, as the indices of its end points and the position
+// of the point along the edge (0 at the first, 1 at the second end point)
+fn ring_edge_position(
+    point: [f64; 2],
+    points: &[[f64; 2]],
+    ring_starts: &[usize],
+) -> Option<(usize, usize, f64)> {
+    let ring_ends = ring_starts.iter().skip(1).copied().chain([points.len()]);
+    for (start, end) in ring_starts.iter().copied().zip(ring_ends) {
+        for i in start..end {
+            let next = if i + 1 == end { start } else { i + 1 };
+            let [a, b] = [points[i], points[next]];
+            let edge = [b[0] - a[0], b[1] - a[1]];
+            let length_squared = edge[0] * edge[0] + edge[1] * edge[1];
+            if length_squared == 0.0 {
+                continue;
+            }
+            let relative = [point[0] - a[0], point[1] - a[1]];
+            let t = (relative[0] * edge[0] + relative[1] * edge[1]) / length_squared;
+            // Distance of the point from the edge, relative to the length of the edge
+            let distance = (relative[0] * edge[1] - relative[1] * edge[0]).abs() / length_squared;
+            if (0.0..=1.0).contains(&t) && distance < 1e-9 {
+                return Some((i, next, t));
+            }
+        }
+    }
+    None
+}
+
+// Even-odd test of a point against all rings, i.e. inside the exterior ring and outside of the
+// holes
+fn inside_rings<'a>(point: [f64; 2], rings: impl IntoIterator<Item = &'a [[f64; 2]]>) -> bool {
+    let mut inside = false;
+    for ring in rings {
+        for (i, a) in ring.iter().enumerate() {
+            let b = ring[(i + 1) % ring.len()];
+            if (a[1] > point[1]) != (b[1] > point[1])
+                && point[0] < (b[0] - a[0]) * (point[1] - a[1]) / (b[1] - a[1]) + a[0]
+            {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+// Polygon of a solid that rays are intersected with. A ray that leaves a polygon of a closed
+// solid towards the outside crosses the other polygons an even number of times, one that
+// leaves it towards the inside an odd number of times.
+pub struct RayTarget {
+    normal: [f64; 3],
+    offset: f64,
+    // Axis-aligned box around the polygon, so rays that miss it are rejected cheaply
+    lower_corner: [f64; 3],
+    upper_corner: [f64; 3],
+    // Axis that is dropped to test the intersection point against the rings in 2D
+    dropped_axis: usize,
+    rings: Vec<Vec<[f64; 2]>>,
+}
+
+impl RayTarget {
+    pub fn new(polygon: &Polygon) -> Option<Self> {
+        let rings: Vec<Vec<[f64; 3]>> = std::iter::once(&polygon.exterior)
+            .chain(&polygon.interior)
+            .map(ring_coordinates)
+            .filter(|ring| ring.len() >= 3)
+            .collect();
+        let exterior = rings.first()?;
+        let normal = newell_normal(exterior);
+        if norm(normal) == 0.0 {
+            return None;
+        }
+        let normal = normalize(normal);
+        let dropped_axis = (0..3)
+            .max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs()))
+            .unwrap_or(2);
+
+        // The box is padded, so rays through the edges of axis-aligned polygons are not lost
+        let padding = 1e-9 * points_extent(exterior).max(1.0);
+        let mut lower_corner = [f64::INFINITY; 3];
+        let mut upper_corner = [f64::NEG_INFINITY; 3];
+        for point in exterior {
+            for axis in 0..3 {
+                lower_corner[axis] = lower_corner[axis].min(point[axis] - padding);
+                upper_corner[axis] = upper_corner[axis].max(point[axis] + padding);
+            }
+        }
+
+        Some(Self {
+            normal,
+            offset: dot(normal, exterior[0]),
+            lower_corner,
+            upper_corner,
+            dropped_axis,
+            rings: rings
+                .iter()
+                .map(|ring| ring.iter().map(|p| drop_axis(*p, dropped_axis)).collect())
+                .collect(),
+        })
+    }
+
+    // Distance along the ray to the polygon, if the ray crosses it
+    fn intersection(&self, origin: [f64; 3], direction: [f64; 3]) -> Option<f64> {
+        if !self.box_is_hit(origin, direction) {
+            return None;
+        }
+        let denominator = dot(self.normal, direction);
+        if denominator.abs() < 1e-9 {
+            return None;
+        }
+        let distance = (self.offset - dot(self.normal, origin)) / denominator;
+        let hit = [0, 1, 2].map(|i| origin[i] + distance * direction[i]);
+        let rings = self.rings.iter().map(Vec::as_slice);
+        inside_rings(drop_axis(hit, self.dropped_axis), rings).then_some(distance)
+    }
+
+    // Slab test of the ray against the box around the polygon
+    fn box_is_hit(&self, origin: [f64; 3], direction: [f64; 3]) -> bool {
+        let mut entry = 0.0_f64;
+        let mut exit = f64::INFINITY;
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                if origin[axis] < self.lower_corner[axis] || origin[axis] > self.upper_corner[axis]
+                {
+                    return false;
+                }
+                continue;
+            }
+            let a = (self.lower_corner[axis] - origin[axis]) / direction[axis];
+            let b = (self.upper_corner[axis] - origin[axis]) / direction[axis];
+            entry = entry.max(a.min(b));
+            exit = exit.min(a.max(b));
+        }
+        entry <= exit
+    }
+}
+
+fn drop_axis(p: [f64; 3], axis: usize) -> [f64; 2] {
+    match axis {
+        0 => [p[1], p[2]],
+        1 => [p[2], p[0]],
+        _ => [p[0], p[1]],
+    }
+}
+
+// Small tilts of the rays, so they rarely run exactly through the edges of other polygons
+const RAY_TILTS: [[f64; 2]; 3] = [[0.013, 0.007], [-0.011, 0.017], [0.019, -0.005]];
+
+// True if the triangulated polygon faces into its solid, given by the other polygons of the
+// solid. Rays are cast along the normal from the centres of the largest triangles; the
+// majority of their crossing parities decides.
+pub fn faces_inward<'a>(
+    triangles: &[u32],
+    points: &[[f64; 3]],
+    other_polygons: impl Iterator<Item = &'a RayTarget> + Clone,
+) -> bool {
+    let normal = polygon_normal(triangles, points);
+    if norm(normal) == 0.0 {
+        return false;
+    }
+    let normal = normalize(normal);
+    let u = normalize(cross(
+        if normal[0].abs() < 0.9 {
+            [1.0, 0.0, 0.0]
+        } else {
+            [0.0, 1.0, 0.0]
+        },
+        normal,
+    ));
+    let v = cross(normal, u);
+
+    let mut samples: Vec<(f64, [f64; 3])> = triangles
+        .chunks_exact(3)
+        .map(|t| {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|i| points[i as usize]);
+            let area = norm(cross(sub(b, a), sub(c, a)));
+            (area, [0, 1, 2].map(|i| (a[i] + b[i] + c[i]) / 3.0))
+        })
+        .collect();
+    samples.sort_by(|a, b| b.0.total_cmp(&a.0));
+    samples.truncate(RAY_TILTS.len());
+
+    let min_distance = 1e-9 * points_extent(points).max(1.0);
+    let inward_votes = samples
+        .iter()
+        .zip(RAY_TILTS)
+        .filter(|((_, origin), [tilt_u, tilt_v])| {
+            let direction = normalize([0, 1, 2].map(|i| normal[i] + tilt_u * u[i] + tilt_v * v[i]));
+            let crossings = other_polygons
+                .clone()
+                .filter_map(|polygon| polygon.intersection(*origin, direction))
+                .filter(|distance| *distance > min_distance)
+                .count();
+            crossings % 2 == 1
+        })
+        .count();
+    inward_votes * 2 > samples.len()
+}
+
+// Reverses the order of the points of all rings, which turns the polygon around
+pub fn reverse_polygon(polygon: &Polygon) -> Option<Polygon> {
+    let reverse_ring = |ring: &LinearRing| -> Option<LinearRing> {
+        let mut points: Vec<DirectPosition> = ring.points().into_iter().cloned().collect();
+        points.reverse();
+        linear_ring(ring.gml.id.clone(), points).ok()
+    };
+
+    let exterior = reverse_ring(&polygon.exterior)?;
+    let interior = polygon
+        .interior
+        .iter()
+        .map(reverse_ring)
+        .collect::<Option<Vec<_>>>()?;
+    Polygon::new(polygon.gml.clone(), exterior, interior).ok()
+}
+
+// Coordinates of a ring without consecutive duplicates and without the closing point
+fn ring_coordinates(ring: &LinearRing) -> Vec<[f64; 3]> {
+    let mut points: Vec<[f64; 3]> = ring.points().iter().map(|p| p.coords()).collect();
+    points.dedup();
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
 
+// Normal of a ring by Newell's method, which averages over all edges, so it is robust against
+// collinear and slightly non-planar vertices. Zero if the ring is degenerate.
+pub fn newell_normal(points: &[[f64; 3]]) -> [f64; 3] {
+    let Some(origin) = points.first() else {
+        return [0.0; 3];
+    };
+    let mut normal = [0.0; 3];
+    for (i, current) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        let [cx, cy, cz] = sub(*current, *origin);
+        let [nx, ny, nz] = sub(next, *origin);
+        normal[0] += (cy - ny) * (cz + nz);
+        normal[1] += (cz - nz) * (cx + nx);
+        normal[2] += (cx - nx) * (cy + ny);
+    }
+    normal
+}
+
+// Orthonormal basis u, v, n of the plane with the given normal, so that u x v = n. None if the
+// normal is too short to be used.
+fn plane_basis(points: &[[f64; 3]], normal: [f64; 3]) -> Option<[[f64; 3]; 3]> {
+    let length = norm(normal);
+    let extent = points_extent(points);
+    if length <= f64::EPSILON * extent * extent {
+        return None;
+    }
+    let n = normal.map(|c| c / length);
+    // Any axis that is not parallel to the normal gives the first direction in the plane
+    let axis = if n[0].abs() < 0.9 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    let u = normalize(cross(axis, n));
+    let v = cross(n, u);
+    Some([u, v, n])
+}
+
+// Projects the points onto the plane, keeping the orientation of the rings as seen from the side
+// the normal points to. Without a plane the axis in which the points extend least is dropped
+// instead.
+fn project_to_plane(points: &[[f64; 3]], plane: Option<[[f64; 3]; 3]>) -> Vec<[f64; 2]> {
+    if let Some([u, v, _]) = plane {
+        return points.iter().map(|p| [dot(*p, u), dot(*p, v)]).collect();
+    }
+
+    // Dominant axis fallback
+    let mut extents = [0.0f64; 3];
+    for point in points {
+        for axis in 0..3 {
+            extents[axis] = extents[axis].max(point[axis].abs());
+        }
+    }
+    let dropped_axis = (0..3)
+        .min_by(|a, b| extents[*a].total_cmp(&extents[*b]))
+        .unwrap_or(2);
+    points.iter().map(|p| drop_axis(*p, dropped_axis)).collect()
+}
+
+// Drops degenerate triangles and winds the others like the ring the normal was derived from
+fn orient_triangles(triangles: &[u32], points: &[[f64; 3]], normal: [f64; 3]) -> Vec<u32> {
+    let extent = points_extent(points);
+    let min_area = f64::EPSILON * extent * extent;
+    triangles
+        .chunks_exact(3)
+        .filter_map(|t| {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|i| points[i as usize]);
+            let triangle_normal = cross(sub(b, a), sub(c, a));
+            if norm(triangle_normal) <= min_area {
+                return None;
+            }
+            if dot(triangle_normal, normal) < 0.0 {
+                Some([t[0], t[2], t[1]])
+            } else {
+                Some([t[0], t[1], t[2]])
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+// Largest absolute coordinate, used to scale the tolerances
+fn points_extent(points: &[[f64; 3]]) -> f64 {
+    points
+        .iter()
+        .flatten()
+        .fold(0.0f64, |extent, c| extent.max(c.abs()))
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let length = norm(a);
+    a.map(|c| c / length)
+}
+
+pub fn construct_buffered_bounding_box(envelope: Option<&Envelope>) -> BoundingBoxGeometry {
     // Get the envelope
-    if let Some(envelope) = input_building.envelope() {
+    if let Some(envelope) = envelope {
         let lower = envelope.lower_corner();
         let upper = envelope.upper_corner();
 
         // Apply 1m buffer
-        let x_min = lower.x() - 2.0;
-        let y_min = lower.y() - 2.0;
-        let z_min = lower.z() - 2.0;
-        let x_max = upper.x() + 2.0;
-        let y_max = upper.y() + 2.0;
-        let z_max = upper.z() + 2.0;
-
-        // Define the 8 corners of the buffered box
-        let corners = vec![
-            [x_min, y_min, z_min],
-            [x_max, y_min, z_min],
-            [x_max, y_max, z_min],
-            [x_min, y_max, z_min],
-            [x_min, y_min, z_max],
-            [x_max, y_min, z_max],
-            [x_max, y_max, z_max],
-            [x_min, y_max, z_max],
-        ];
+        let lower_corner = [lower.x() - 2.0, lower.y() - 2.0, lower.z() - 2.0];
+        let upper_corner = [upper.x() + 2.0, upper.y() + 2.0, upper.z() + 2.0];
 
-        // Add corners to vertices list
-        vertices.extend_from_slice(&corners);
-
-        let base_index = vertices.len() as u64;
-
-        // For each corner, add a small triangle reflector
-        let mut next_index = base_index;
-
-        let offset = 0.5; // size of the corner reflector
-
-        for &corner in &corners {
-            // Place the tip of the pyramid offset along all axes
-            let tip = [
-                corner[0] + offset * (if corner[0] == x_min { 1.0 } else { -1.0 }),
-                corner[1] + offset * (if corner[1] == y_min { 1.0 } else { -1.0 }),
-                corner[2] + offset * (if corner[2] == z_min { 1.0 } else { -1.0 }),
-            ];
-
-            // Add tip vertex
-            vertices.push(tip);
-            let tip_idx = next_index;
-            next_index += 1;
-
-            // Create triangle faces from tip to 3 adjacent box edges
-            let corner_idx = vertices.iter().position(|&v| v == corner).unwrap() as u64;
-
-            // Choose 3 neighbors on the bounding box that share the same corner
-            // For simplicity, just add 3 edges from the corner to its neighbors in x, y, z
-            let neighbors = [
-                [corner[0], corner[1], tip[2]], // z neighbor
-                [corner[0], tip[1], corner[2]], // y neighbor
-                [tip[0], corner[1], corner[2]], // x neighbor
-            ];
-
-            let mut neighbor_indices = Vec::new();
-            for neighbor in neighbors {
-                let idx = vertices.iter().position(|&v| v == neighbor);
-                let n_idx = if let Some(i) = idx {
-                    i as u64
-                } else {
-                    vertices.push(neighbor);
-                    let i = next_index;
-                    next_index += 1;
-                    i
-                };
-                neighbor_indices.push(n_idx);
-            }
+        return construct_bounding_box_reflectors(lower_corner, upper_corner);
+    }
+
+    println!("Envelope hat keine gültigen lower/upper corner Koordinaten.");
+    (Vec::new(), Vec::new())
+}
 
-            // Triangles from tip to each of the 3 neighbor edges
-            for &n_idx in &neighbor_indices {
-                indices.push([tip_idx, corner_idx, n_idx]);
+// Envelope of all multi surfaces of the collected city objects, restricted to a single level of
+// detail if one is given. The polygons of solids are read into multi surfaces by the feature
+// parsing, so the solids collected by ecitygml (which may only consist of references) are not
+// taken into account.
+pub fn collected_envelope<'a>(
+    city_objects: impl Iterator<Item = &'a CityObjectGeometryCollection>,
+    lod: Option<LevelOfDetail>,
+) -> Option<Envelope> {
+    let envelopes: Vec<Envelope> = city_objects
+        .flat_map(|city_object| city_object.multi_surfaces.iter())
+        .filter(|(l, _)| lod.is_none_or(|lod| **l == lod))
+        .map(|(_, multi_surface)| multi_surface.envelope())
+        .collect();
+
+    Envelope::from_envelopes(&envelopes).ok()
+}
+
+// Applies a point transformation (e.g. a reprojection) to all multi surfaces of the collected
+// city objects. The gml:ids are kept; polygons with points that cannot be transformed or that
+// degenerate are dropped, and so are multi surfaces without any polygon left.
+pub fn transform_city_objects<'a>(
+    city_objects: impl Iterator<Item = &'a mut CityObjectGeometryCollection>,
+    transform: &impl Fn([f64; 3]) -> Option<[f64; 3]>,
+) {
+    for city_object in city_objects {
+        city_object.multi_surfaces = std::mem::take(&mut city_object.multi_surfaces)
+            .into_iter()
+            .filter_map(|(lod, multi_surface)| {
+                Some((lod, transform_multi_surface(&multi_surface, transform)?))
+            })
+            .collect();
+    }
+}
+
+pub fn transform_multi_surface(
+    multi_surface: &MultiSurface,
+    transform: &impl Fn([f64; 3]) -> Option<[f64; 3]>,
+) -> Option<MultiSurface> {
+    let polygons = multi_surface
+        .surface_member()
+        .iter()
+        .filter_map(|polygon| {
+            let transformed = transform_polygon(polygon, transform);
+            if transformed.is_none() {
+                eprintln!("Polygon {} could not be transformed.", polygon.gml.id);
             }
+            transformed
+        })
+        .collect();
+    MultiSurface::new(multi_surface.gml.clone(), polygons).ok()
+}
+
+fn transform_polygon(
+    polygon: &Polygon,
+    transform: &impl Fn([f64; 3]) -> Option<[f64; 3]>,
+) -> Option<Polygon> {
+    let transform_ring = |ring: &LinearRing| -> Option<LinearRing> {
+        let points = ring
+            .points()
+            .into_iter()
+            .map(|p| {
+                let [x, y, z] = transform(p.coords())?;
+                DirectPosition::new(x, y, z).ok()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        linear_ring(ring.gml.id.clone(), points).ok()
+    };
+
+    let exterior = transform_ring(&polygon.exterior)?;
+    let interior = polygon
+        .interior
+        .iter()
+        .map(transform_ring)
+        .collect::<Option<Vec<_>>>()?;
+    Polygon::new(polygon.gml.clone(), exterior, interior).ok()
+}
+
+// Builds the corner reflector geometry for an axis-aligned box given by its lower and upper corner
+pub fn construct_bounding_box_reflectors(
+    lower_corner: [f64; 3],
+    upper_corner: [f64; 3],
+) -> BoundingBoxGeometry {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    let [x_min, y_min, z_min] = lower_corner;
+    let [x_max, y_max, z_max] = upper_corner;
+
+    // Define the 8 corners of the box
+    let corners = vec![
+        [x_min, y_min, z_min],
+        [x_max, y_min, z_min],
+        [x_max, y_max, z_min],
+        [x_min, y_max, z_min],
+        [x_min, y_min, z_max],
+        [x_max, y_min, z_max],
+        [x_max, y_max, z_max],
+        [x_min, y_max, z_max],
+    ];
+
+    // Add corners to vertices list
+    vertices.extend_from_slice(&corners);
+
+    let base_index = vertices.len() as u64;
+
+    // For each corner, add a small triangle reflector
+    let mut next_index = base_index;
+
+    let offset = 0.5; // size of the corner reflector
+
+    for &corner in &corners {
+        // Place the tip of the pyramid offset along all axes
+        let tip = [
+            corner[0] + offset * (if corner[0] == x_min { 1.0 } else { -1.0 }),
+            corner[1] + offset * (if corner[1] == y_min { 1.0 } else { -1.0 }),
+            corner[2] + offset * (if corner[2] == z_min { 1.0 } else { -1.0 }),
+        ];
+
+        // Add tip vertex
+        vertices.push(tip);
+        let tip_idx = next_index;
+        next_index += 1;
+
+        // Create triangle faces from tip to 3 adjacent box edges
+        let corner_idx = vertices.iter().position(|&v| v == corner).unwrap() as u64;
+
+        // Choose 3 neighbors on the bounding box that share the same corner
+        // For simplicity, just add 3 edges from the corner to its neighbors in x, y, z
+        let neighbors = [
+            [corner[0], corner[1], tip[2]], // z neighbor
+            [corner[0], tip[1], corner[2]], // y neighbor
+            [tip[0], corner[1], corner[2]], // x neighbor
+        ];
+
+        let mut neighbor_indices = Vec::new();
+        for neighbor in neighbors {
+            let idx = vertices.iter().position(|&v| v == neighbor);
+            let n_idx = if let Some(i) = idx {
+                i as u64
+            } else {
+                vertices.push(neighbor);
+                let i = next_index;
+                next_index += 1;
+                i
+            };
+            neighbor_indices.push(n_idx);
         }
 
-        // Done
-        return (vertices, indices);
+        // Triangles from tip to each of the 3 neighbor edges
+        for &n_idx in &neighbor_indices {
+            indices.push([tip_idx, corner_idx, n_idx]);
+        }
     }
 
-    println!("Envelope hat keine gültigen lower/upper corner Koordinaten.");
     (vertices, indices)
 }
 
-pub fn import_bounding_box(path_to_bounding_box: &str){
-    // todo: muss noch implementiert werden
+// Transforms the corners of an axis-aligned box and returns the box around them
+pub fn transform_box(
+    [x_min, y_min, z_min]: [f64; 3],
+    [x_max, y_max, z_max]: [f64; 3],
+    transform: &impl Fn([f64; 3]) -> Option<[f64; 3]>,
+) -> Option<([f64; 3], [f64; 3])> {
+    let mut lower_corner = [f64::INFINITY; 3];
+    let mut upper_corner = [f64::NEG_INFINITY; 3];
+    for x in [x_min, x_max] {
+        for y in [y_min, y_max] {
+            for z in [z_min, z_max] {
+                let corner = transform([x, y, z])?;
+                for axis in 0..3 {
+                    lower_corner[axis] = lower_corner[axis].min(corner[axis]);
+                    upper_corner[axis] = upper_corner[axis].max(corner[axis]);
+                }
+            }
+        }
+    }
+    Some((lower_corner, upper_corner))
+}
+
+// Looks up the imported bounding box of a building and builds the same reflector geometry
+// as for the bounding boxes derived from the envelope. If the coordinates are transformed, the
+// box is the axis-aligned box around its transformed corners.
+pub fn import_bounding_box(
+    building_id: &str,
+    imported_bboxes: &ImportedBoundingBoxes,
+    transform: &impl Fn([f64; 3]) -> Option<[f64; 3]>,
+) -> Option<BoundingBoxGeometry> {
+    let imported_bbox = imported_bboxes.get(building_id)?;
+    let (lower_corner, upper_corner) = transform_box(
+        imported_bbox.lower_corner,
+        imported_bbox.upper_corner,
+        transform,
+    )?;
+
+    Some(construct_bounding_box_reflectors(
+        lower_corner,
+        upper_corner,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egml::model::base::{Gml, Id};
+
+    fn ring(id: &str, points: &[[f64; 3]]) -> LinearRing {
+        let points = points
+            .iter()
+            .map(|[x, y, z]| DirectPosition::new(*x, *y, *z).unwrap())
+            .collect();
+        linear_ring(Id::try_from(id).unwrap(), points).unwrap()
+    }
+
+    fn polygon(exterior: &[[f64; 3]], interiors: &[&[[f64; 3]]]) -> Polygon {
+        let interiors = interiors
+            .iter()
+            .enumerate()
+            .map(|(index, interior)| ring(&format!("hole_{}", index), interior))
+            .collect();
+        Polygon::new(
+            Gml::new(Id::try_from("polygon").unwrap()),
+            ring("exterior", exterior),
+            interiors,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn local_origins_are_parsed() {
+        for (name, origin) in [
+            ("envelope-center", LocalOrigin::EnvelopeCenter),
+            ("envelope-lower", LocalOrigin::EnvelopeLower),
+            ("vertex-centroid", LocalOrigin::VertexCentroid),
+            ("ground-centroid", LocalOrigin::GroundCentroid),
+            (
+                "fixed:690000, 5336000.5,-12",
+                LocalOrigin::Fixed([690000.0, 5336000.5, -12.0]),
+            ),
+        ] {
+            assert_eq!(name.parse::<LocalOrigin>(), Ok(origin), "{}", name);
+        }
+        for name in [
+            "centre",
+            "fixed:1,2",
+            "fixed:1,2,3,4",
+            "fixed:1,x,3",
+            "fixed:",
+        ] {
+            assert!(name.parse::<LocalOrigin>().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn output_transform_matrix_maps_like_apply() {
+        let translation = [-690000.0, -5336000.0, -500.0];
+        for transform in [
+            OutputTransform::default(),
+            OutputTransform {
+                up_axis: UpAxis::Y,
+                scale: 100.0,
+                offset: [1.0, 2.0, 3.0],
+            },
+            OutputTransform {
+                up_axis: UpAxis::Z,
+                scale: 0.5,
+                offset: [-4.0, 0.0, 7.5],
+            },
+        ] {
+            let matrix = transform.matrix(translation);
+            for point in [[690000.0, 5336000.0, 500.0], [690012.5, 5335990.0, 531.25]] {
+                let expected = transform.apply(translate(point, translation));
+                let homogeneous = [point[0], point[1], point[2], 1.0];
+                for (row, expected) in matrix.iter().zip(expected) {
+                    let value: f64 = row.iter().zip(homogeneous).map(|(m, p)| m * p).sum();
+                    assert!((value - expected).abs() < 1e-6, "{:?}", transform);
+                }
+            }
+            assert_eq!(matrix[3], [0.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn y_up_keeps_the_winding_of_triangles() {
+        let transform = OutputTransform {
+            up_axis: UpAxis::Y,
+            ..Default::default()
+        };
+        let [a, b, c] =
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].map(|p| transform.apply(p));
+
+        // The upward normal of the triangle becomes the +y axis
+        assert_eq!(unit_normal(cross(sub(b, a), sub(c, a))), [0.0, 1.0, 0.0]);
+    }
+
+    fn translate(point: [f64; 3], translation: [f64; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|i| point[i] + translation[i])
+    }
+
+    // Area of the triangles, which must all face the side the normal points to
+    fn triangle_area(triangles: &[u32], points: &[[f64; 3]], normal: [f64; 3]) -> f64 {
+        for t in triangles.chunks_exact(3) {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|i| points[i as usize]);
+            assert!(dot(cross(sub(b, a), sub(c, a)), normal) > 0.0, "{:?}", t);
+        }
+        norm(polygon_normal(triangles, points)) / 2.0
+    }
+
+    #[test]
+    fn vertical_walls_are_triangulated_along_their_newell_normal() {
+        // A wall in the xz plane at projected coordinates, starting with collinear points
+        let wall = [
+            [0.0, 0.0, 0.0],
+            [5.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [10.0, 0.0, 6.0],
+            [0.0, 0.0, 6.0],
+        ]
+        .map(|[x, y, z]| [690000.0 + x, 5336000.0 + y, 500.0 + z]);
+
+        let normal = unit_normal(newell_normal(&wall));
+        assert!((normal[1] + 1.0).abs() < 1e-12, "{:?}", normal);
+
+        for triangulator in [
+            Triangulator::Earcut,
+            Triangulator::Cdt {
+                min_angle: None,
+                max_edge_length: None,
+            },
+        ] {
+            let (triangles, points) = triangulate(&polygon(&wall, &[]), triangulator);
+
+            assert_eq!(triangles.len(), 3 * 3, "{:?}", triangulator);
+            let area = triangle_area(&triangles, &points, normal);
+            assert!((area - 60.0).abs() < 1e-6, "{:?}: {}", triangulator, area);
+        }
+    }
+
+    // Distance of a point from the segment between a and b
+    fn segment_distance(point: [f64; 3], a: [f64; 3], b: [f64; 3]) -> f64 {
+        let edge = sub(b, a);
+        let t = (dot(sub(point, a), edge) / dot(edge, edge)).clamp(0.0, 1.0);
+        norm(sub(point, [0, 1, 2].map(|i| a[i] + edge[i] * t)))
+    }
+
+    #[test]
+    fn cdt_leaves_holes_empty() {
+        let exterior = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [10.0, 10.0, 0.0],
+            [0.0, 10.0, 0.0],
+        ];
+        // The hole is wound against the exterior ring, as in CityGML
+        let hole = [
+            [3.0, 3.0, 0.0],
+            [3.0, 7.0, 0.0],
+            [7.0, 7.0, 0.0],
+            [7.0, 3.0, 0.0],
+        ];
+        let inside_hole = |[x, y, _]: [f64; 3]| x > 3.0 && x < 7.0 && y > 3.0 && y < 7.0;
+
+        for (min_angle, max_edge_length) in [(None, None), (Some(25.0), Some(1.5))] {
+            let triangulator = Triangulator::Cdt {
+                min_angle,
+                max_edge_length,
+            };
+            let (triangles, points) = triangulate(&polygon(&exterior, &[&hole]), triangulator);
+
+            for t in triangles.chunks_exact(3) {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| points[i as usize]);
+                let centre = [0, 1, 2].map(|i| (a[i] + b[i] + c[i]) / 3.0);
+                assert!(!inside_hole(centre), "{:?}: {:?}", triangulator, [a, b, c]);
+            }
+            assert!(
+                points.iter().all(|p| !inside_hole(*p)),
+                "{:?}",
+                triangulator
+            );
+            let area = triangle_area(&triangles, &points, [0.0, 0.0, 1.0]);
+            assert!((area - 84.0).abs() < 1e-6, "{:?}: {}", triangulator, area);
+        }
+    }
+
+    #[test]
+    fn steiner_points_on_ring_edges_lie_on_the_edges_in_3d() {
+        // A non-planar quadrilateral, whose horizontal mean plane does not contain its edges
+        let exterior = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 1.0],
+            [10.0, 10.0, 0.0],
+            [0.0, 10.0, 1.0],
+        ];
+        let triangulator = Triangulator::Cdt {
+            min_angle: None,
+            max_edge_length: Some(2.0),
+        };
+
+        let (triangles, points) = triangulate(&polygon(&exterior, &[]), triangulator);
+
+        assert!(!triangles.is_empty());
+        let edges: Vec<([f64; 3], [f64; 3])> = (0..4)
+            .map(|i| (exterior[i], exterior[(i + 1) % 4]))
+            .collect();
+        let mut boundary_points = 0;
+        for point in &points[exterior.len()..] {
+            let flat = |p: [f64; 3]| [p[0], p[1], 0.0];
+            for (a, b) in &edges {
+                if segment_distance(flat(*point), flat(*a), flat(*b)) < 1e-9 {
+                    assert!(segment_distance(*point, *a, *b) < 1e-9, "{:?}", point);
+                    boundary_points += 1;
+                }
+            }
+        }
+        assert!(boundary_points > 0);
+    }
 }
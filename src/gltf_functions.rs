@@ -0,0 +1,338 @@
+use crate::error_functions::{Error, Result, write_error};
+use crate::geometry_functions::{BoundingBoxGeometry, UpAxis};
+use crate::write_functions::{BOUNDING_BOX_MATERIAL, MaterialPalette, UNKNOWN_MATERIAL};
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_TRIANGLES: u32 = 4;
+
+// Triangles of one semantic surface that become one primitive of the building mesh
+#[derive(Debug)]
+pub struct GltfPrimitive<'a> {
+    pub name: &'a str,
+    pub class: &'a str,
+    pub vertices: &'a [[f64; 3]],
+    pub triangles: &'a [u32],
+    pub parent_gml_ids: &'a [String],
+    pub multi_surface_gml_ids: &'a [String],
+    pub polygon_gml_ids: &'a [String],
+}
+
+// Accumulates the binary buffer together with the buffer views and accessors pointing into it
+#[derive(Default)]
+struct GltfBuffer {
+    data: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl GltfBuffer {
+    fn push_positions(&mut self, positions: &[[f32; 3]]) -> usize {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+
+        let bytes: Vec<u8> = positions
+            .iter()
+            .flatten()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        let buffer_view = self.push_buffer_view(&bytes, GLTF_ARRAY_BUFFER);
+
+        self.accessors.push(json!({
+            "bufferView": buffer_view,
+            "componentType": GLTF_FLOAT,
+            "count": positions.len(),
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let buffer_view = self.push_buffer_view(&bytes, GLTF_ELEMENT_ARRAY_BUFFER);
+
+        self.accessors.push(json!({
+            "bufferView": buffer_view,
+            "componentType": GLTF_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn push_buffer_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        // All buffer views start at a 4 byte boundary
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.data.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.data.extend_from_slice(bytes);
+        self.buffer_views.len() - 1
+    }
+}
+
+// Converts a position into glTF's Y-up convention, unless it is Y-up already
+fn to_gltf_axes([x, y, z]: [f64; 3], up_axis: UpAxis) -> [f64; 3] {
+    match up_axis {
+        UpAxis::Y => [x, y, z],
+        UpAxis::Z => [x, z, -y],
+    }
+}
+
+// Converts a position into a glTF position relative to the origin
+fn to_gltf_position(point: &[f64; 3], origin: &[f64; 3], up_axis: UpAxis) -> [f32; 3] {
+    let relative = [
+        point[0] - origin[0],
+        point[1] - origin[1],
+        point[2] - origin[2],
+    ];
+    to_gltf_axes(relative, up_axis).map(|c| c as f32)
+}
+
+// A city object of a glb file: a node with one mesh, whose primitives have f32 positions
+// relative to the origin of the node
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+    pub building_id: String,
+    origin: [f64; 3],
+    up_axis: UpAxis,
+    primitives: Vec<GltfNodePrimitive>,
+}
+
+#[derive(Debug, Clone)]
+struct GltfNodePrimitive {
+    class: String,
+    positions: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+    extras: Value,
+}
+
+// Builds the node of a building. The node is translated to the centre of its geometry, so the
+// f32 positions of the primitives stay precise even for large projected coordinates. Every
+// semantic surface becomes a primitive carrying its gml:ids and class in the extras.
+pub fn gltf_node(
+    building_id: &str,
+    primitives: &[GltfPrimitive],
+    bbox: &BoundingBoxGeometry,
+    up_axis: UpAxis,
+) -> Result<GltfNode> {
+    let all_points = primitives.iter().flat_map(|p| p.vertices.iter());
+    let Some(origin) = bounding_box_centre(all_points) else {
+        return Err(Error::NoGeometry(building_id.to_string()));
+    };
+
+    let mut node_primitives = Vec::new();
+    for primitive in primitives {
+        if primitive.triangles.is_empty() || !primitive.triangles.len().is_multiple_of(3) {
+            continue;
+        }
+        node_primitives.push(GltfNodePrimitive {
+            class: primitive.class.to_string(),
+            positions: primitive
+                .vertices
+                .iter()
+                .map(|p| to_gltf_position(p, &origin, up_axis))
+                .collect(),
+            indices: primitive.triangles.to_vec(),
+            extras: json!({
+                "gml_id": primitive.name,
+                "class": primitive.class,
+                "parent_gml_ids": primitive.parent_gml_ids,
+                "multi_surface_gml_ids": primitive.multi_surface_gml_ids,
+                "polygon_gml_ids": primitive.polygon_gml_ids,
+            }),
+        });
+    }
+
+    // The bounding box reflectors are added as an additional primitive
+    let (bbox_vertices, bbox_triangles) = bbox;
+    if !bbox_vertices.is_empty() {
+        node_primitives.push(GltfNodePrimitive {
+            class: BOUNDING_BOX_MATERIAL.to_string(),
+            positions: bbox_vertices
+                .iter()
+                .map(|p| to_gltf_position(p, &origin, up_axis))
+                .collect(),
+            indices: bbox_triangles.iter().flatten().map(|i| *i as u32).collect(),
+            extras: json!({ "class": BOUNDING_BOX_MATERIAL }),
+        });
+    }
+
+    if node_primitives.is_empty() {
+        return Err(Error::NoGeometry(building_id.to_string()));
+    }
+    Ok(GltfNode {
+        building_id: building_id.to_string(),
+        origin,
+        up_axis,
+        primitives: node_primitives,
+    })
+}
+
+// Writes the city objects as binary glTF (<file_stem>.glb) with one named node per city object
+// in a single scene
+pub fn write_glb_file(
+    file_stem: &str,
+    nodes: &[GltfNode],
+    palette: Option<&MaterialPalette>,
+    output_dir: &Path,
+) -> Result<()> {
+    if nodes.is_empty() {
+        return Err(Error::NoGeometry(file_stem.to_string()));
+    }
+
+    let mut buffer = GltfBuffer::default();
+    let mut materials: Vec<Value> = Vec::new();
+    let mut material_names: Vec<String> = Vec::new();
+    let mut material_index = |class: &str| -> Option<usize> {
+        let palette = palette?;
+        if let Some(index) = material_names.iter().position(|m| m == class) {
+            return Some(index);
+        }
+        let [r, g, b] = palette
+            .get(class)
+            .or_else(|| palette.get(UNKNOWN_MATERIAL))
+            .copied()
+            .unwrap_or([0.5, 0.5, 0.5]);
+        materials.push(json!({
+            "name": class,
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [r, g, b, 1.0],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+            "doubleSided": true,
+        }));
+        material_names.push(class.to_string());
+        Some(material_names.len() - 1)
+    };
+
+    let mut gltf_nodes: Vec<Value> = Vec::new();
+    let mut meshes: Vec<Value> = Vec::new();
+    for node in nodes {
+        let mut gltf_primitives: Vec<Value> = Vec::new();
+        for primitive in &node.primitives {
+            let position_accessor = buffer.push_positions(&primitive.positions);
+            let index_accessor = buffer.push_indices(&primitive.indices);
+
+            let mut gltf_primitive = json!({
+                "attributes": { "POSITION": position_accessor },
+                "indices": index_accessor,
+                "mode": GLTF_TRIANGLES,
+                "extras": primitive.extras,
+            });
+            if let Some(material) = material_index(&primitive.class) {
+                gltf_primitive["material"] = json!(material);
+            }
+            gltf_primitives.push(gltf_primitive);
+        }
+
+        gltf_nodes.push(json!({
+            "name": node.building_id,
+            "mesh": meshes.len(),
+            "translation": to_gltf_axes(node.origin, node.up_axis),
+            "extras": { "building_id": node.building_id, "origin": node.origin },
+        }));
+        meshes.push(json!({ "name": node.building_id, "primitives": gltf_primitives }));
+    }
+
+    while !buffer.data.len().is_multiple_of(4) {
+        buffer.data.push(0);
+    }
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "RustCityGML2OBJ" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..gltf_nodes.len()).collect::<Vec<_>>() }],
+        "nodes": gltf_nodes,
+        "meshes": meshes,
+        "accessors": buffer.accessors,
+        "bufferViews": buffer.buffer_views,
+        "buffers": [{ "byteLength": buffer.data.len() }],
+    });
+    if !materials.is_empty() {
+        document["materials"] = json!(materials);
+    }
+
+    let file_path = output_dir.join(format!("{}.glb", file_stem));
+    let file = File::create(&file_path).map_err(write_error(&file_path))?;
+    let mut writer = BufWriter::new(file);
+    write_glb_content(&mut writer, &document, &buffer.data)
+        .and_then(|_| writer.flush())
+        .map_err(write_error(&file_path))
+}
+
+fn write_glb_content(
+    writer: &mut impl Write,
+    document: &Value,
+    binary: &[u8],
+) -> std::io::Result<()> {
+    // The JSON chunk is padded with spaces to a 4 byte boundary
+    let mut json_chunk = serde_json::to_vec(document)?;
+    while !json_chunk.len().is_multiple_of(4) {
+        json_chunk.push(b' ');
+    }
+
+    let total_length = 12 + 8 + json_chunk.len() + 8 + binary.len();
+
+    writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+    writer.write_all(&GLB_VERSION.to_le_bytes())?;
+    writer.write_all(&(total_length as u32).to_le_bytes())?;
+
+    writer.write_all(&(json_chunk.len() as u32).to_le_bytes())?;
+    writer.write_all(&GLB_CHUNK_JSON.to_le_bytes())?;
+    writer.write_all(&json_chunk)?;
+
+    writer.write_all(&(binary.len() as u32).to_le_bytes())?;
+    writer.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+    writer.write_all(binary)?;
+
+    writer.flush()
+}
+
+fn bounding_box_centre<'a>(points: impl Iterator<Item = &'a [f64; 3]>) -> Option<[f64; 3]> {
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    let mut is_empty = true;
+    for point in points {
+        is_empty = false;
+        for axis in 0..3 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+
+    if is_empty {
+        return None;
+    }
+    Some([
+        (min[0] + max[0]) / 2.0,
+        (min[1] + max[1]) / 2.0,
+        (min[2] + max[2]) / 2.0,
+    ])
+}
@@ -0,0 +1,345 @@
+use crate::error_functions::{Error, Result};
+use flate2::read::GzDecoder;
+use glob::Pattern;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+const CITYGML_NAMESPACE_PREFIX: &str = "http://www.opengis.net/citygml/";
+
+// File formats that can be converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Citygml,
+    CityJson,
+    CityJsonSeq,
+}
+
+// Derives the format from the file extension: .gml and .xml for CityGML, .json (including
+// .city.json) for CityJSON and .jsonl for CityJSONSeq. A trailing .gz (e.g. .gml.gz) is ignored.
+pub fn input_format(path: &Path) -> Option<InputFormat> {
+    let path = if is_gzip_file(path) {
+        Path::new(path.file_stem()?)
+    } else {
+        path
+    };
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "gml" | "xml" => Some(InputFormat::Citygml),
+        "json" => Some(InputFormat::CityJson),
+        "jsonl" => Some(InputFormat::CityJsonSeq),
+        _ => None,
+    }
+}
+
+// Whether a document of the given format is to be converted. JSON documents are only CityJSON
+// if their (first) object has "type": "CityJSON", so other JSON files next to the data (e.g. a
+// manifest.json or metadata) are skipped. Malformed JSON is kept, so its error is reported.
+pub fn is_convertible_document(format: Option<InputFormat>, content: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Header {
+        #[serde(rename = "type")]
+        object_type: Option<String>,
+    }
+
+    let header = match format {
+        Some(InputFormat::CityJson) => content,
+        Some(InputFormat::CityJsonSeq) => content
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default(),
+        Some(InputFormat::Citygml) | None => return true,
+    };
+    match serde_json::from_str::<Header>(header) {
+        Ok(header) => header.object_type.as_deref() == Some("CityJSON"),
+        Err(_) => serde_json::from_str::<IgnoredAny>(header).is_err(),
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+pub fn is_gzip_file(path: &Path) -> bool {
+    has_extension(path, "gz")
+}
+
+pub fn is_zip_archive(path: &Path) -> bool {
+    has_extension(path, "zip")
+}
+
+// Files that are converted: CityGML, CityJSON and CityJSONSeq files (optionally gzipped) and
+// zip archives containing them
+pub fn is_input_file(path: &Path) -> bool {
+    input_format(path).is_some() || is_zip_archive(path)
+}
+
+// An input file together with the directory (relative to the input directory it was found in)
+// its output is written to, so that the output mirrors the input directory structure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFile {
+    pub path: PathBuf,
+    pub relative_dir: PathBuf,
+}
+
+// Collects the files to convert from the given files and directories. Directories are searched
+// non-recursively unless `recursive` is set. Files found in directories are only taken if their
+// path relative to that directory matches one of the include patterns (if any) and none of the
+// exclude patterns, e.g. "12/**/*.gml" or "*_old.gml". Files that are given explicitly are always
+// taken if their format is known.
+pub fn discover_input_files(
+    inputs: &[PathBuf],
+    recursive: bool,
+    include: &[Pattern],
+    exclude: &[Pattern],
+) -> Vec<InputFile> {
+    let mut input_files = Vec::new();
+
+    for input in inputs {
+        if input.is_file() {
+            if is_input_file(input) {
+                input_files.push(InputFile {
+                    path: input.clone(),
+                    relative_dir: PathBuf::new(),
+                });
+            } else {
+                eprintln!("Unknown input format of file {}", input.display());
+            }
+            continue;
+        }
+
+        let mut paths = Vec::new();
+        collect_files(input, recursive, &mut HashSet::new(), &mut paths);
+        paths.sort();
+
+        for path in paths {
+            let Ok(relative_path) = path.strip_prefix(input) else {
+                continue;
+            };
+            let is_included =
+                include.is_empty() || include.iter().any(|p| p.matches_path(relative_path));
+            let is_excluded = exclude.iter().any(|p| p.matches_path(relative_path));
+            if !is_input_file(&path) || !is_included || is_excluded {
+                continue;
+            }
+
+            input_files.push(InputFile {
+                relative_dir: relative_path
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default(),
+                path,
+            });
+        }
+    }
+
+    input_files
+}
+
+// Directories are recursed into once: symbolic links to directories are followed, but a
+// directory that was already visited (e.g. through a symlink loop) is skipped.
+fn collect_files(
+    dir: &Path,
+    recursive: bool,
+    visited_dirs: &mut HashSet<PathBuf>,
+    paths: &mut Vec<PathBuf>,
+) {
+    if let Ok(canonical_dir) = fs::canonicalize(dir)
+        && !visited_dirs.insert(canonical_dir)
+    {
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Could not read input directory {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let is_dir = if file_type.is_symlink() {
+            // The metadata of the target; dangling links are skipped
+            match fs::metadata(&path) {
+                Ok(metadata) => metadata.is_dir(),
+                Err(_) => continue,
+            }
+        } else {
+            file_type.is_dir()
+        };
+        if is_dir {
+            if recursive {
+                collect_files(&path, recursive, visited_dirs, paths);
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+}
+
+// CityGML versions that can be converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CitygmlVersion {
+    V1_0,
+    V2_0,
+    V3_0,
+}
+
+impl fmt::Display for CitygmlVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CitygmlVersion::V1_0 => write!(f, "1.0"),
+            CitygmlVersion::V2_0 => write!(f, "2.0"),
+            CitygmlVersion::V3_0 => write!(f, "3.0"),
+        }
+    }
+}
+
+// Detects the CityGML version from the namespaces declared on the root element (e.g.
+// http://www.opengis.net/citygml/2.0 or http://www.opengis.net/citygml/building/2.0).
+// Returns None if no CityGML namespace is declared there.
+pub fn detect_citygml_version(xml_document: &str) -> Option<CitygmlVersion> {
+    let mut reader = Reader::from_str(xml_document);
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                return e
+                    .attributes()
+                    .flatten()
+                    .filter(|a| a.key.as_ref().starts_with(b"xmlns"))
+                    .filter_map(|a| a.unescape_value().ok())
+                    .filter(|namespace| namespace.starts_with(CITYGML_NAMESPACE_PREFIX))
+                    .find_map(|namespace| match namespace.rsplit('/').next() {
+                        Some("1.0") => Some(CitygmlVersion::V1_0),
+                        Some("2.0") => Some(CitygmlVersion::V2_0),
+                        Some("3.0") => Some(CitygmlVersion::V3_0),
+                        _ => None,
+                    });
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => (),
+        }
+    }
+}
+
+// The content of a single input file or archive member
+#[derive(Debug, Clone)]
+pub struct InputDocument {
+    // Path of the file, with the path of the member appended for archive members, e.g.
+    // tiles/12.zip/12/2200/1340.gml; used in log messages and the metadata
+    pub name: String,
+    // Directory of the archive member within the archive, empty for plain files
+    pub relative_dir: PathBuf,
+    pub format: Option<InputFormat>,
+    pub content: String,
+}
+
+// Reads an input file and passes its documents one after another to `convert`: plain files and
+// gzipped files yield one document, zip archives one per CityGML, CityJSON or CityJSONSeq member.
+// JSON documents that are not CityJSON are skipped. Archive members are read from the archive
+// directly without extracting them to disk; members that cannot be read are passed on as errors.
+// Every document is decompressed into memory in full, since the parsers work on the whole text.
+pub fn read_input_documents(
+    path: &Path,
+    mut convert: impl FnMut(Result<InputDocument>),
+) -> Result<()> {
+    let read_error = |source| Error::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+    let archive_error = |source| Error::Archive {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if is_zip_archive(path) {
+        let file = File::open(path).map_err(read_error)?;
+        let mut archive = ZipArchive::new(BufReader::new(file)).map_err(archive_error)?;
+        for index in 0..archive.len() {
+            let mut member = match archive.by_index(index) {
+                Ok(member) => member,
+                Err(e) => {
+                    convert(Err(archive_error(e)));
+                    continue;
+                }
+            };
+            // Members with unsafe paths (e.g. containing "..") are skipped
+            let Some(member_path) = member.enclosed_name() else {
+                continue;
+            };
+            if member.is_dir() || input_format(&member_path).is_none() {
+                continue;
+            }
+
+            let name = format!("{}/{}", path.display(), member_path.display());
+            let content = if is_gzip_file(&member_path) {
+                read_to_string(GzDecoder::new(&mut member))
+            } else {
+                read_to_string(&mut member)
+            };
+            if content
+                .as_ref()
+                .is_ok_and(|content| !is_convertible_document(input_format(&member_path), content))
+            {
+                println!("Skipping {}, it is not a CityJSON file.", name);
+                continue;
+            }
+            convert(
+                content
+                    .map(|content| InputDocument {
+                        relative_dir: member_path
+                            .parent()
+                            .map(Path::to_path_buf)
+                            .unwrap_or_default(),
+                        format: input_format(&member_path),
+                        name: name.clone(),
+                        content,
+                    })
+                    .map_err(|source| Error::Read {
+                        path: PathBuf::from(name),
+                        source,
+                    }),
+            );
+        }
+        return Ok(());
+    }
+
+    let file = BufReader::new(File::open(path).map_err(read_error)?);
+    let content = if is_gzip_file(path) {
+        read_to_string(GzDecoder::new(file))
+    } else {
+        read_to_string(file)
+    }
+    .map_err(read_error)?;
+    if !is_convertible_document(input_format(path), &content) {
+        println!("Skipping {}, it is not a CityJSON file.", path.display());
+        return Ok(());
+    }
+    convert(Ok(InputDocument {
+        name: path.display().to_string(),
+        relative_dir: PathBuf::new(),
+        format: input_format(path),
+        content,
+    }));
+    Ok(())
+}
+
+fn read_to_string(mut reader: impl Read) -> io::Result<String> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    Ok(content)
+}
@@ -0,0 +1,451 @@
+//! Conversion of CityGML 1.0, 2.0 and 3.0 and CityJSON city models into triangulated OBJ files.
+//!
+//! The same pipeline that drives the `RustCityGML2OBJ` command line tool can be embedded
+//! into other Rust code:
+//!
+//! ```no_run
+//! use rust_citygml2obj::{ConversionOptions, convert_file};
+//! use std::path::Path;
+//!
+//! let options = ConversionOptions {
+//!     add_json: true,
+//!     ..ConversionOptions::new("/data/obj")
+//! };
+//! convert_file(Path::new("/data/citygml/tile.gml"), &options).expect("conversion should work");
+//! ```
+
+pub mod cityjson_functions;
+pub mod conversion_functions;
+pub mod crs_functions;
+pub mod error_functions;
+pub mod feature_functions;
+pub mod geometry_functions;
+pub mod gltf_functions;
+pub mod input_functions;
+pub mod write_functions;
+
+use crate::cityjson_functions::{
+    CityJsonGeometry, parse_cityjson, parse_cityjson_seq, write_cityjson_file,
+};
+use crate::conversion_functions::CityObjectOutput;
+use crate::crs_functions::{CrsTransformation, detect_source_crs};
+use crate::error_functions::{ConversionSummary, Error, Result};
+use crate::feature_functions::{CityObjectFeature, ParsedCityObjects, parse_city_objects};
+use crate::geometry_functions::{
+    LocalOrigin, Normals, OutputTransform, Triangulator, collected_envelope, transform_box,
+};
+use crate::gltf_functions::write_glb_file;
+use crate::input_functions::{
+    CitygmlVersion, InputDocument, InputFormat, detect_citygml_version, read_input_documents,
+};
+use crate::write_functions::{ImportedBoundingBoxes, MaterialPalette, write_mtl_file};
+use ecitygml_core::model::city_model::CitygmlModel;
+use ecitygml_core::model::common::{CityObjectClass, LevelOfDetail};
+use egml::model::base::Id;
+use egml::model::geometry::{DirectPosition, Envelope};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::io::{self, Cursor};
+use std::panic;
+use std::path::{Path, PathBuf};
+
+/// Options controlling the conversion of a CityGML model into OBJ files.
+#[derive(Debug, Clone, Default)]
+pub struct ConversionOptions {
+    /// Directory the OBJ and JSON files are written to.
+    pub output: PathBuf,
+    /// Building-wise translation into a local CRS.
+    pub tbw: bool,
+    /// Origin of the local CRS of every city object with `tbw`. Use [`LocalOrigin::Fixed`], e.g.
+    /// with the centre of [`document_envelope`], to keep neighbouring city objects aligned.
+    pub origin: LocalOrigin,
+    /// Scale, up axis and offset of the written coordinates, applied after the translation.
+    pub output_transform: OutputTransform,
+    /// Algorithm the polygons are triangulated with. Minimum angles of a refined constrained
+    /// Delaunay triangulation above [`geometry_functions::MAX_MIN_ANGLE`] are clamped to it.
+    pub triangulator: Triangulator,
+    /// Write `vn` normals with flat or smooth shading into the OBJ files.
+    pub normals: Option<Normals>,
+    /// Turn polygons that face into their solid around, against the CityGML ring orientation.
+    pub repair_orientation: bool,
+    /// Add the building-wise, axis-aligned bounding box derived from the envelope.
+    pub add_bb: bool,
+    /// Write an additional `<building_id>.json` metadata file per building.
+    pub add_json: bool,
+    /// Bounding boxes keyed by building gml:id, taking precedence over `add_bb`.
+    pub imported_bboxes: Option<ImportedBoundingBoxes>,
+    /// Group the output by semantic class (e.g. all WallSurfaces of a building).
+    pub group_sc: bool,
+    /// Group the output by semantic component (e.g. an individual WallSurface).
+    pub group_scomp: bool,
+    /// Write one OBJ file per building with an object per semantic component.
+    pub group_building: bool,
+    /// Write one binary glTF (`.glb`) file per input document, named after the `source`, with a
+    /// node per building instead of the per-polygon OBJ files.
+    pub write_glb: bool,
+    /// Write one CityJSON 2.0 (`.city.json`) file per input document, named after the `source`,
+    /// with the triangles or the original polygons, instead of the per-polygon OBJ files.
+    pub cityjson: Option<CityJsonGeometry>,
+    /// Levels of detail in the order they are tried; the first one a city object has geometry
+    /// in is converted. If empty, all levels of detail are converted.
+    pub lods: Vec<LevelOfDetail>,
+    /// Write a material library with these class colours and reference it from every OBJ file.
+    pub material_palette: Option<MaterialPalette>,
+    /// Name of the converted file or archive member, recorded as `source` in the JSON metadata
+    /// and naming the `.glb` and `.city.json` files; set for every document by [`convert_file`].
+    pub source: Option<String>,
+    /// EPSG code of the CRS all coordinates are reprojected into (see
+    /// [`crs_functions::proj_definition`] for the supported ones).
+    pub target_crs: Option<u32>,
+    /// EPSG code of the CRS of the input, used if a document does not name its CRS.
+    pub source_crs: Option<u32>,
+}
+
+impl ConversionOptions {
+    pub fn new(output: impl Into<PathBuf>) -> Self {
+        Self {
+            output: output.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Reads a CityGML, CityJSON or CityJSONSeq file (see [`input_functions::input_format`]), which
+/// may be gzipped or a zip archive of such files, and converts all of its city objects with
+/// [`convert_document`].
+///
+/// Every document is converted with its name (e.g. `tiles/12.zip/12/2200/1340.gml` for an
+/// archive member) as [`ConversionOptions::source`]. Archive members that cannot be read or
+/// converted are reported and counted as failed files in the returned summary; an error is only
+/// returned if the file itself cannot be read.
+pub fn convert_file(path: &Path, options: &ConversionOptions) -> Result<ConversionSummary> {
+    let mut summary = ConversionSummary::default();
+    read_input_documents(path, |document| {
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path.display(), e);
+                summary += ConversionSummary::file_failed();
+                return;
+            }
+        };
+        let options = ConversionOptions {
+            source: Some(document.name.clone()),
+            ..options.clone()
+        };
+        match convert_document(&document, &options) {
+            Ok(document_summary) => summary += document_summary,
+            Err(e) => {
+                eprintln!("Error converting {}: {}", document.name, e);
+                summary += ConversionSummary::file_failed();
+            }
+        }
+    })?;
+    Ok(summary)
+}
+
+/// Converts all city objects of a single input document, i.e. the content of a file or an
+/// archive member, and returns the counts of converted and failed city objects.
+///
+/// The CityGML version is detected from the namespaces of the document. ecitygml only reads
+/// CityGML 3.0, so the city objects of CityGML 1.0 and 2.0 documents are taken entirely from
+/// [`parse_city_objects`], and those of CityJSON documents from [`parse_cityjson`].
+pub fn convert_document(
+    document: &InputDocument,
+    options: &ConversionOptions,
+) -> Result<ConversionSummary> {
+    let content = &document.content;
+
+    let (mut data, parsed_city_objects) = match document.format {
+        Some(InputFormat::CityJson) => (CitygmlModel::default(), parse_cityjson(content)?),
+        Some(InputFormat::CityJsonSeq) => (CitygmlModel::default(), parse_cityjson_seq(content)?),
+        Some(InputFormat::Citygml) | None => {
+            // Parsed first, so malformed XML is reported before ecitygml reads the document
+            let parsed_city_objects = parse_city_objects(content)?;
+            let data = match detect_citygml_version(content) {
+                Some(version @ (CitygmlVersion::V1_0 | CitygmlVersion::V2_0)) => {
+                    println!("Reading CityGML {} file {}", version, document.name);
+                    CitygmlModel::default()
+                }
+                _ => read_citygml_model(content)?,
+            };
+            (data, parsed_city_objects)
+        }
+    };
+
+    let crs = crs_transformation(options, Some(document))?;
+    // The CityJSON output names the CRS of the written coordinates
+    let epsg = crs
+        .as_ref()
+        .map(|crs| crs.target_epsg)
+        .or_else(|| detect_source_crs(document))
+        .or(options.source_crs);
+    let summary = convert_city_model(&mut data, &parsed_city_objects, options, crs.as_ref(), epsg)?;
+    Ok(ConversionSummary {
+        files_converted: 1,
+        ..summary
+    })
+}
+
+/// Returns the envelope of all city objects of a document in the target CRS of the options.
+///
+/// The envelopes of all documents of a dataset give an origin that is shared by all of its city
+/// objects (see [`ConversionOptions::origin`]). Only [`parse_city_objects`] is used for CityGML
+/// documents, so the envelope is derived without reading the document with ecitygml.
+pub fn document_envelope(
+    document: &InputDocument,
+    options: &ConversionOptions,
+) -> Result<Option<Envelope>> {
+    let parsed_city_objects = match document.format {
+        Some(InputFormat::CityJson) => parse_cityjson(&document.content)?,
+        Some(InputFormat::CityJsonSeq) => parse_cityjson_seq(&document.content)?,
+        Some(InputFormat::Citygml) | None => parse_city_objects(&document.content)?,
+    };
+    let Some(envelope) = collected_envelope(
+        parsed_city_objects
+            .values()
+            .flat_map(|city_object| &city_object.members)
+            .map(|member| &member.geometry),
+        None,
+    ) else {
+        return Ok(None);
+    };
+
+    let crs = crs_transformation(options, Some(document))?;
+    let transform_point = |point: [f64; 3]| match &crs {
+        Some(crs) => crs.transform_point(point),
+        None => Some(point),
+    };
+    let corners = transform_box(
+        envelope.lower_corner().coords(),
+        envelope.upper_corner().coords(),
+        &transform_point,
+    );
+    Ok(corners.and_then(|(lower_corner, upper_corner)| {
+        Envelope::new(
+            DirectPosition::new(lower_corner[0], lower_corner[1], lower_corner[2]).ok()?,
+            DirectPosition::new(upper_corner[0], upper_corner[1], upper_corner[2]).ok()?,
+        )
+        .ok()
+    }))
+}
+
+// The reprojection into the target CRS, if one is given. The CRS named in the document takes
+// precedence over the source CRS of the options.
+fn crs_transformation(
+    options: &ConversionOptions,
+    document: Option<&InputDocument>,
+) -> Result<Option<CrsTransformation>> {
+    let Some(target_crs) = options.target_crs else {
+        return Ok(None);
+    };
+    let source_crs = document
+        .and_then(detect_source_crs)
+        .or(options.source_crs)
+        .ok_or_else(|| {
+            Error::Crs("the document names no CRS and no source CRS is given".to_string())
+        })?;
+    // CityJSON always has the x axis first, GML follows the axis order of the CRS
+    let latitude_first = document
+        .is_none_or(|document| matches!(document.format, Some(InputFormat::Citygml) | None));
+    CrsTransformation::new(source_crs, target_crs, latitude_first).map(Some)
+}
+
+// True if both paths exist and name the same file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// ecitygml panics on some invalid documents instead of returning an error, so its panics are
+// turned into errors of the document
+fn read_citygml_model(content: &str) -> Result<CitygmlModel> {
+    let read = || ecitygml_io::CitygmlReader::new(Cursor::new(content.as_bytes())).finish();
+    match panic::catch_unwind(read) {
+        Ok(result) => Ok(result?),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|m| m.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(Error::CitygmlReader(message))
+        }
+    }
+}
+
+/// Converts all city objects of an already parsed CityGML model.
+///
+/// `parsed_city_objects` holds the city objects and members (building parts, installations,
+/// bridges, water bodies, ...) that ecitygml does not read (see [`parse_city_objects`]); it
+/// may be empty, in which case only the content of `model` is converted. Parsed city objects
+/// that are not part of `model` are converted on their own.
+///
+/// City objects that cannot be written are reported and counted in the returned summary; an
+/// error is only returned if the material library, the binary glTF or the CityJSON file cannot
+/// be written.
+pub fn convert_model(
+    model: &mut CitygmlModel,
+    parsed_city_objects: &ParsedCityObjects,
+    options: &ConversionOptions,
+) -> Result<ConversionSummary> {
+    let crs = crs_transformation(options, None)?;
+    let epsg = crs
+        .as_ref()
+        .map(|crs| crs.target_epsg)
+        .or(options.source_crs);
+    convert_city_model(model, parsed_city_objects, options, crs.as_ref(), epsg)
+}
+
+// `epsg` is the CRS of the written coordinates, if it is known
+fn convert_city_model(
+    model: &mut CitygmlModel,
+    parsed_city_objects: &ParsedCityObjects,
+    options: &ConversionOptions,
+    crs: Option<&CrsTransformation>,
+    epsg: Option<u32>,
+) -> Result<ConversionSummary> {
+    if let Some(palette) = &options.material_palette {
+        write_mtl_file(palette, &options.output)?;
+    }
+
+    let model_ids: HashSet<String> = model
+        .building
+        .iter()
+        .map(|x| &x.occupied_space.space.city_object.gml.id)
+        .chain(
+            model
+                .city_furniture
+                .iter()
+                .map(|x| &x.occupied_space.space.city_object.gml.id),
+        )
+        .chain(model.road.iter().map(|x| &x.space.city_object.gml.id))
+        .chain(
+            model
+                .solitary_vegetation_object
+                .iter()
+                .map(|x| &x.occupied_space.space.city_object.gml.id),
+        )
+        .map(|id| id.to_string())
+        .collect();
+
+    let members = |id: &Id| -> &[CityObjectFeature] {
+        parsed_city_objects
+            .get(&id.to_string())
+            .map(|city_object| city_object.members.as_slice())
+            .unwrap_or_default()
+    };
+
+    let mut outputs: Vec<CityObjectOutput> = model
+        .building
+        .par_iter_mut()
+        .map(|building| {
+            let building_id = building.occupied_space.space.city_object.gml.id.clone();
+            conversion_functions::collect_building_geometries(
+                building,
+                members(&building_id),
+                options,
+                crs,
+            )
+        })
+        .collect();
+
+    outputs.par_extend(model.city_furniture.par_iter().map(|city_furniture| {
+        let id = &city_furniture.occupied_space.space.city_object.gml.id;
+        conversion_functions::collect_city_object_geometries(
+            city_furniture,
+            id,
+            CityObjectClass::CityFurniture,
+            members(id),
+            options,
+            crs,
+        )
+    }));
+
+    outputs.par_extend(model.road.par_iter().map(|road| {
+        let id = &road.space.city_object.gml.id;
+        conversion_functions::collect_city_object_geometries(
+            road,
+            id,
+            CityObjectClass::Road,
+            members(id),
+            options,
+            crs,
+        )
+    }));
+
+    outputs.par_extend(model.solitary_vegetation_object.par_iter().map(
+        |solitary_vegetation_object| {
+            let id = &solitary_vegetation_object
+                .occupied_space
+                .space
+                .city_object
+                .gml
+                .id;
+            conversion_functions::collect_city_object_geometries(
+                solitary_vegetation_object,
+                id,
+                CityObjectClass::SolitaryVegetationObject,
+                members(id),
+                options,
+                crs,
+            )
+        },
+    ));
+
+    // Bridges, tunnels, water bodies etc. and all city objects of CityGML 1.0 and 2.0 files are
+    // only known from the additional parsing
+    outputs.par_extend(
+        parsed_city_objects
+            .par_iter()
+            .filter(|(id, _)| !model_ids.contains(*id))
+            .map(|(_, city_object)| {
+                conversion_functions::collect_parsed_city_object_geometries(
+                    city_object,
+                    options,
+                    crs,
+                )
+            }),
+    );
+
+    let mut summary = ConversionSummary::default();
+    let mut gltf_nodes = Vec::new();
+    let mut cityjson_city_objects = Vec::new();
+    for output in outputs {
+        summary += output.summary;
+        gltf_nodes.extend(output.gltf_node);
+        cityjson_city_objects.extend(output.cityjson);
+    }
+
+    // All city objects of the document are written into one binary glTF file with a node each
+    if options.write_glb && !gltf_nodes.is_empty() {
+        gltf_nodes.sort_by(|a, b| a.building_id.cmp(&b.building_id));
+        write_glb_file(
+            &conversion_functions::document_file_stem(options),
+            &gltf_nodes,
+            options.material_palette.as_ref(),
+            &options.output,
+        )?;
+    }
+
+    // All city objects of the document are written into one CityJSON file
+    if options.cityjson.is_some() && !cityjson_city_objects.is_empty() {
+        cityjson_city_objects.sort_by(|a, b| a.city_object_id.cmp(&b.city_object_id));
+        let file_stem = conversion_functions::document_file_stem(options);
+        // An input CityJSON file in the output directory is not overwritten
+        let file_path = options.output.join(format!("{}.city.json", file_stem));
+        if let Some(source) = &options.source
+            && is_same_file(Path::new(source), &file_path)
+        {
+            return Err(Error::Write {
+                path: file_path,
+                source: io::Error::new(io::ErrorKind::AlreadyExists, "it is the input file"),
+            });
+        }
+        write_cityjson_file(&file_stem, &cityjson_city_objects, epsg, &options.output)?;
+    }
+
+    Ok(summary)
+}
@@ -1,19 +1,40 @@
-mod conversion_functions;
-mod geometry_functions;
-mod translation_module;
-mod write_functions;
-
 use clap::Parser;
-use rayon::prelude::*;
+use egml::model::geometry::Envelope;
+use glob::Pattern;
+use rust_citygml2obj::cityjson_functions::CityJsonGeometry;
+use rust_citygml2obj::crs_functions;
+use rust_citygml2obj::error_functions::{ConversionSummary, write_error};
+use rust_citygml2obj::geometry_functions::{
+    self, LocalOrigin, Normals, OutputTransform, Triangulator, UpAxis,
+};
+use rust_citygml2obj::write_functions::{InputFileManifest, Manifest};
+use rust_citygml2obj::{
+    ConversionOptions, conversion_functions, convert_document, document_envelope, input_functions,
+    write_functions,
+};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    // Input file path
-    #[arg(short, long)]
-    input: String,
+    // Input files or directories, e.g. "-i tiles" or "-i a.gml b.city.json"
+    #[arg(short, long, num_args = 1.., required = true)]
+    input: Vec<PathBuf>,
+
+    // Option for searching the input directories recursively
+    #[arg(long, default_value_t = false)]
+    recursive: bool,
+
+    // Glob patterns (relative to the input directory) of the files to convert, e.g. "12/**/*.gml"
+    #[arg(long, value_delimiter = ',')]
+    include: Vec<String>,
+
+    // Glob patterns (relative to the input directory) of the files to skip
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
 
     // Output directory
     #[arg(short, long)]
@@ -23,6 +44,46 @@ struct Args {
     #[arg(long, default_value_t = false)]
     tbw: bool,
 
+    // Origin of the local CRS with --tbw: envelope-center (default), envelope-lower,
+    // vertex-centroid, ground-centroid, fixed:x,y,z or dataset-center, implies --tbw
+    #[arg(long)]
+    origin: Option<String>,
+
+    // Axis that points up in the written coordinates, y (game engines, DCC tools) or z (CityGML)
+    #[arg(long, default_value = "z")]
+    up_axis: UpAxis,
+
+    // Factor all written coordinates are scaled by, e.g. 100 for centimetres
+    #[arg(long, default_value_t = 1.0)]
+    scale: f64,
+
+    // Offset x,y,z added to all written coordinates, in the written units and axes
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    offset: Vec<f64>,
+
+    // Algorithm the polygons are triangulated with, earcut (default) or cdt (constrained Delaunay)
+    #[arg(long)]
+    triangulator: Option<Triangulator>,
+
+    // Refinement of the cdt triangulation with Steiner points
+    #[arg(
+        long,
+        help = "Minimum angle in degrees (above 0, at most 30) the cdt triangulation is refined to, implies --triangulator cdt"
+    )]
+    min_angle: Option<f64>,
+
+    // Maximum edge length the cdt triangulation is refined to with Steiner points, implies --triangulator cdt
+    #[arg(long)]
+    max_edge_length: Option<f64>,
+
+    // Option for writing vn normals into the obj files, flat (per polygon) or smooth (per vertex within a surface)
+    #[arg(long)]
+    normals: Option<Normals>,
+
+    // Option for turning polygons around that face into their building instead of outwards
+    #[arg(long, default_value_t = false)]
+    repair_orientation: bool,
+
     // Option for additionally writing out a json file containing metadata
     #[arg(long, default_value_t = false)]
     add_json: bool,
@@ -31,9 +92,13 @@ struct Args {
     #[arg(long, default_value_t = false)]
     add_bb: bool,
 
-    // Option for importing a bounding box instead of creating a new one from the data
-    #[arg(long, default_value_t = false)]
-    import_bb: bool,
+    // Option for importing the bounding boxes from a JSON or CSV file instead of creating new ones from the data
+    #[arg(long)]
+    import_bb: Option<String>,
+
+    // Levels of detail in the order they are tried, e.g. "2" or "3,2,1"; all are converted if not given
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u8).range(0..=3))]
+    lod: Vec<u8>,
 
     // Option for grouping the polygons by semantic surfaces
     #[arg(long, default_value_t = false)]
@@ -42,55 +107,405 @@ struct Args {
     // Option for grouping the polygons by semantic surfaces
     #[arg(long, default_value_t = false)]
     group_scomp: bool,
+
+    // Option for writing the whole building into one obj file with a group per semantic surface
+    #[arg(long, default_value_t = false)]
+    group_building: bool,
+
+    // Option for writing one binary glTF (.glb) file per input file, with a node per building,
+    // instead of the per-polygon obj files
+    #[arg(long, default_value_t = false)]
+    glb: bool,
+
+    // Option for writing one CityJSON 2.0 (.city.json) file per input file with the triangulated geometry
+    #[arg(long, default_value_t = false)]
+    cityjson: bool,
+
+    // Option for writing the original polygons instead of the triangles into the CityJSON files, implies --cityjson
+    #[arg(long, default_value_t = false)]
+    cityjson_polygons: bool,
+
+    // Option for writing a material library with one colour per semantic class
+    #[arg(long, default_value_t = false)]
+    add_mtl: bool,
+
+    // Option for importing a custom class colour palette (JSON) for the material library, implies --add-mtl
+    #[arg(long)]
+    palette: Option<String>,
+
+    // CRS all coordinates are reprojected into, e.g. "EPSG:25832"
+    #[arg(long)]
+    target_crs: Option<String>,
+
+    // CRS of input files that do not name one in an srsName (CityGML) or referenceSystem (CityJSON)
+    #[arg(long)]
+    source_crs: Option<String>,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
-    println!("Input Directory: {}", args.input);
+    println!("Input: {:?}", args.input);
+    println!("recursive: {}", args.recursive);
+    println!("include: {:?}", args.include);
+    println!("exclude: {:?}", args.exclude);
     println!("Output Directory: {}", args.output);
-    println!("translate buildings into local crs: {}", args.tbw);
-    println!("add bounding box: {}", args.add_json);
-    println!("import bounding box: {}", args.import_bb);
+    println!(
+        "translate buildings into local crs: {}",
+        args.tbw || args.origin.is_some()
+    );
+    println!("local origin: {:?}", args.origin);
+    println!("up axis: {:?}", args.up_axis);
+    println!("scale: {}", args.scale);
+    println!("offset: {:?}", args.offset);
+    println!("triangulator: {:?}", args.triangulator);
+    println!("minimum angle: {:?}", args.min_angle);
+    println!("maximum edge length: {:?}", args.max_edge_length);
+    println!("normals: {:?}", args.normals);
+    println!("repair orientation: {}", args.repair_orientation);
+    println!("add bounding box: {}", args.add_bb);
+    println!("add json metadata: {}", args.add_json);
+    println!("import bounding box: {:?}", args.import_bb);
+    println!("levels of detail: {:?}", args.lod);
     println!("group output by semantic class: {}", args.group_sc);
     println!("group output by semantic component: {}", args.group_scomp);
+    println!("group output by building: {}", args.group_building);
+    println!("write glb: {}", args.glb);
+    println!("target crs: {:?}", args.target_crs);
+    println!("source crs: {:?}", args.source_crs);
+    println!(
+        "write cityjson: {}",
+        args.cityjson || args.cityjson_polygons
+    );
+    println!(
+        "add material library: {}",
+        args.add_mtl || args.palette.is_some()
+    );
+
+    // Import the external bounding boxes once for all input files
+    let imported_bboxes = match &args.import_bb {
+        Some(path) => match write_functions::import_bbox_from_file(Path::new(path)) {
+            Ok(imported_bboxes) => {
+                println!("Imported bounding boxes: {}", imported_bboxes.len());
+                Some(imported_bboxes)
+            }
+            Err(e) => {
+                eprintln!("Could not import bounding boxes: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    // Use the default colours unless a custom palette is given
+    let material_palette = match &args.palette {
+        Some(path) => match write_functions::import_palette_from_file(Path::new(path)) {
+            Ok(palette) => Some(palette),
+            Err(e) => {
+                eprintln!("Could not import palette: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None if args.add_mtl => Some(write_functions::default_material_palette()),
+        None => None,
+    };
 
-    // Read directory entries
-    let input_path = Path::new(&args.input);
-    let entries = fs::read_dir(input_path).expect("Could not read input directory");
-
-    // Filter and process each matching file
-    for entry in entries.flatten() {
-        let path = entry.path();
-
-        // Check if the file ends with a valid extension
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            let ext = ext.to_lowercase();
-            if ext == "gml" || ext == "xml" {
-                println!("Processing file: {}", path.display());
-
-                let reader_result = ecitygml_io::CitygmlReader::from_path(&path);
-
-                match reader_result.unwrap().finish() {
-                    Ok(mut data) => {
-                        let all_buildings = &mut data.building;
-
-                        all_buildings.par_iter_mut().for_each(|building| {
-                            conversion_functions::collect_building_geometries(
-                                building,
-                                args.tbw,
-                                args.add_bb,
-                                args.add_json,
-                                args.import_bb,
-                                args.group_sc,
-                                args.group_scomp,
-                            );
+    // Only CRSs with an offline definition can be used
+    let parse_crs_arg = |name: &Option<String>| -> Result<Option<u32>, String> {
+        let Some(name) = name else {
+            return Ok(None);
+        };
+        match crs_functions::parse_crs(name) {
+            Some(epsg) if crs_functions::proj_definition(epsg).is_some() => Ok(Some(epsg)),
+            _ => Err(format!("Unsupported coordinate reference system: {}", name)),
+        }
+    };
+    let (target_crs, source_crs) = match (
+        parse_crs_arg(&args.target_crs),
+        parse_crs_arg(&args.source_crs),
+    ) {
+        (Ok(target_crs), Ok(source_crs)) => (target_crs, source_crs),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The dataset centre is only known after all input files have been read, so it is resolved
+    // into a fixed origin below
+    let dataset_center = args.origin.as_deref() == Some("dataset-center");
+    let origin = match args.origin.as_deref() {
+        Some("dataset-center") | None => LocalOrigin::default(),
+        Some(origin) => match origin.parse() {
+            Ok(origin) => origin,
+            Err(e) => {
+                eprintln!("Invalid origin: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+
+    // A negative scale would mirror the geometry and flip the winding of the triangles
+    if !(args.scale.is_finite() && args.scale > 0.0) {
+        eprintln!("Invalid scale {}: must be positive", args.scale);
+        return ExitCode::FAILURE;
+    }
+    let offset = match args.offset[..] {
+        [] => [0.0; 3],
+        [x, y, z] => [x, y, z],
+        _ => {
+            eprintln!("Invalid offset {:?}: must be x,y,z", args.offset);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The refinement only exists for the constrained Delaunay triangulation
+    let refined = args.min_angle.is_some() || args.max_edge_length.is_some();
+    let triangulator = match args.triangulator {
+        Some(Triangulator::Earcut) if refined => {
+            eprintln!("--min-angle and --max-edge-length need --triangulator cdt");
+            return ExitCode::FAILURE;
+        }
+        Some(Triangulator::Cdt { .. }) => Triangulator::Cdt {
+            min_angle: args.min_angle,
+            max_edge_length: args.max_edge_length,
+        },
+        None if refined => Triangulator::Cdt {
+            min_angle: args.min_angle,
+            max_edge_length: args.max_edge_length,
+        },
+        Some(Triangulator::Earcut) | None => Triangulator::Earcut,
+    };
+    if args
+        .min_angle
+        .is_some_and(|angle| !(angle > 0.0 && angle <= geometry_functions::MAX_MIN_ANGLE))
+    {
+        // The refinement does not finish for larger angles
+        eprintln!(
+            "Invalid minimum angle: must be above 0 and at most {} degrees",
+            geometry_functions::MAX_MIN_ANGLE
+        );
+        return ExitCode::FAILURE;
+    }
+    if args
+        .max_edge_length
+        .is_some_and(|length| !(length.is_finite() && length > 0.0))
+    {
+        eprintln!("Invalid maximum edge length: must be positive");
+        return ExitCode::FAILURE;
+    }
+
+    let mut options = ConversionOptions {
+        tbw: args.tbw || args.origin.is_some(),
+        origin,
+        triangulator,
+        normals: args.normals,
+        repair_orientation: args.repair_orientation,
+        output_transform: OutputTransform {
+            up_axis: args.up_axis,
+            scale: args.scale,
+            offset,
+        },
+        add_bb: args.add_bb,
+        add_json: args.add_json,
+        imported_bboxes,
+        lods: args
+            .lod
+            .iter()
+            .filter_map(|lod| conversion_functions::level_of_detail_from_number(*lod))
+            .collect(),
+        group_sc: args.group_sc,
+        group_scomp: args.group_scomp,
+        group_building: args.group_building,
+        write_glb: args.glb,
+        cityjson: match (args.cityjson_polygons, args.cityjson) {
+            (true, _) => Some(CityJsonGeometry::Polygons),
+            (false, true) => Some(CityJsonGeometry::Triangles),
+            (false, false) => None,
+        },
+        material_palette,
+        target_crs,
+        source_crs,
+        ..ConversionOptions::new(&args.output)
+    };
+
+    // An invalid pattern would silently disable the filter, so it is rejected
+    let (include, exclude) = match (parse_patterns(&args.include), parse_patterns(&args.exclude)) {
+        (Ok(include), Ok(exclude)) => (include, exclude),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let input_files =
+        input_functions::discover_input_files(&args.input, args.recursive, &include, &exclude);
+    if input_files.is_empty() {
+        eprintln!("No input files found.");
+        return ExitCode::FAILURE;
+    }
+
+    // All city objects share the centre of the envelope of all input files as their origin, so
+    // they stay aligned to each other across files
+    if dataset_center {
+        let mut envelopes = Vec::new();
+        for input_file in &input_files {
+            let _ = input_functions::read_input_documents(&input_file.path, |document| {
+                if let Ok(Some(envelope)) =
+                    document.and_then(|document| document_envelope(&document, &options))
+                {
+                    envelopes.push(envelope);
+                }
+            });
+        }
+        match Envelope::from_envelopes(&envelopes) {
+            Ok(envelope) => {
+                let center = geometry_functions::envelope_center(&envelope);
+                println!("dataset center: {:?}", center);
+                options.origin = LocalOrigin::Fixed(center);
+            }
+            Err(_) => println!("No geometry to derive the dataset center from."),
+        }
+    }
+
+    // The output mirrors the directory structure of the input, including the directories of
+    // the members of zip archives
+    let output_root = PathBuf::from(&args.output);
+    let mut summary = ConversionSummary::default();
+    let mut manifest_input_files = Vec::new();
+    let mut file_failed = |path: String, error: String| {
+        eprintln!("{}", error);
+        summary += ConversionSummary::file_failed();
+        manifest_input_files.push(InputFileManifest {
+            path,
+            converted: false,
+            error: Some(error),
+        });
+    };
+    let mut converted_summaries = Vec::new();
+    // Output files relative to the output root, with the document that wrote them
+    let mut written_files: HashMap<String, String> = HashMap::new();
+    for input_file in input_files {
+        println!("Processing file: {}", input_file.path.display());
+        let input_path = input_file.path.display().to_string();
+        let result = input_functions::read_input_documents(&input_file.path, |document| {
+            let document = match document {
+                Ok(document) => document,
+                Err(e) => {
+                    file_failed(
+                        input_path.clone(),
+                        format!("Error reading {}: {}", input_path, e),
+                    );
+                    return;
+                }
+            };
+
+            let relative_dir = input_file.relative_dir.join(&document.relative_dir);
+            options.output = output_root.join(&relative_dir);
+            if let Err(e) = fs::create_dir_all(&options.output) {
+                file_failed(
+                    document.name.clone(),
+                    format!(
+                        "Could not create output directory {}: {}",
+                        options.output.display(),
+                        e
+                    ),
+                );
+                return;
+            }
+
+            if input_functions::is_zip_archive(&input_file.path) {
+                println!("Processing archive member: {}", document.name);
+            }
+            options.source = Some(document.name.clone());
+            match convert_document(&document, &options) {
+                Ok(mut document_summary) => {
+                    // The manifest lists the files relative to the output directory of the run
+                    for city_object in &mut document_summary.city_objects {
+                        for file in &mut city_object.files {
+                            *file = relative_dir.join(&file).display().to_string();
+                        }
+                    }
+                    // Documents from different inputs can mirror to the same output directory
+                    // (e.g. in/sub/x.gml and the member sub/x.gml of an archive), so a document
+                    // whose files were already written by another one fails the run
+                    let collision = document_summary
+                        .city_objects
+                        .iter()
+                        .flat_map(|city_object| &city_object.files)
+                        .find_map(|file| {
+                            written_files
+                                .get(file)
+                                .filter(|writer| **writer != document.name)
+                                .map(|writer| (file.clone(), writer.clone()))
                         });
+                    if let Some((file, writer)) = collision {
+                        file_failed(
+                            document.name.clone(),
+                            format!(
+                                "Error converting {}: it overwrote {}, written by {}",
+                                document.name, file, writer
+                            ),
+                        );
+                        return;
                     }
-                    Err(e) => {
-                        eprintln!("Error reading file {}: {:?}", path.display(), e);
+                    for city_object in &document_summary.city_objects {
+                        for file in &city_object.files {
+                            written_files.insert(file.clone(), document.name.clone());
+                        }
                     }
+                    converted_summaries.push((document.name, document_summary));
                 }
+                Err(e) => file_failed(
+                    document.name.clone(),
+                    format!("Error converting {}: {}", document.name, e),
+                ),
             }
+        });
+        if let Err(e) = result {
+            file_failed(
+                input_path.clone(),
+                format!("Error reading file {}: {}", input_path, e),
+            );
         }
     }
+
+    for (path, document_summary) in converted_summaries {
+        manifest_input_files.push(InputFileManifest {
+            path,
+            converted: true,
+            error: None,
+        });
+        summary += document_summary;
+    }
+    manifest_input_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // Batch jobs can detect partial conversions from the exit code
+    println!("{}", summary);
+    let exit_code = summary.exit_code();
+
+    // The manifest indexes the whole output, so it is written into the output root
+    let mut city_objects = std::mem::take(&mut summary.city_objects);
+    city_objects.sort_by(|a, b| (&a.source, &a.building_id).cmp(&(&b.source, &b.building_id)));
+    let manifest = Manifest {
+        input_files: manifest_input_files,
+        summary,
+        city_objects,
+    };
+    if let Err(e) = fs::create_dir_all(&output_root)
+        .map_err(write_error(&output_root))
+        .and_then(|_| write_functions::write_manifest(&manifest, &output_root))
+    {
+        eprintln!("Could not write manifest: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::from(exit_code)
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>, String> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| format!("Invalid glob pattern {}: {}", pattern, e))
+        })
+        .collect()
 }
@@ -1,9 +1,12 @@
-use crate::Args;
-use clap::Parser;
+use crate::error_functions::{ConversionSummary, Error, Result, write_error};
+use crate::geometry_functions::BoundingBoxGeometry;
 use egml::model::base::Id;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs::File;
 use std::io::{BufWriter, Write};
+use std::ops::AddAssign;
 use std::path::Path;
 
 #[derive(Debug)]
@@ -13,188 +16,716 @@ pub enum SemanticSurfaceId<'a> {
     None,
 }
 
-impl<'a> ToString for SemanticSurfaceId<'a> {
-    fn to_string(&self) -> String {
+impl fmt::Display for SemanticSurfaceId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            SemanticSurfaceId::Id(id) => id.to_string(),
-            SemanticSurfaceId::Str(s) => s.to_string(),
-            SemanticSurfaceId::None => String::new(),
+            SemanticSurfaceId::Id(id) => write!(f, "{}", id),
+            SemanticSurfaceId::Str(s) => write!(f, "{}", s),
+            SemanticSurfaceId::None => Ok(()),
         }
     }
 }
 
-#[derive(Serialize)]
-struct Metadata {
-    building_id: String,
-    class_gml_id: String,
-    multi_surface_gml_id: String,
-    polygon_gml_id: String,
-    thematic_role: String,
-    dx: String,
-    dy: String,
-    dz: String,
+// Metadata of a single written file, listing the gml:ids of all polygons it contains
+#[derive(Debug, Serialize)]
+pub struct FileMetadata {
+    pub file_name: String,
+    pub class: String,
+    pub parent_gml_ids: Vec<String>,
+    pub thematic_surface_gml_ids: Vec<String>,
+    pub multi_surface_gml_ids: Vec<String>,
+    pub polygon_gml_ids: Vec<String>,
 }
 
-pub fn write_json_metadata(
-    building_id: &Id,
-    semantic_surface_id: SemanticSurfaceId,
-    thematic_role: &str,
-    output_dir: &str,
-    dx: f64,
-    dy: f64,
-    dz: f64,
-    gml_id: &Id,
-    stuff_gml_id: &Id,
-) {
-    let mut semantic_surface_string: String = "default".to_string();
-
-    match semantic_surface_id {
-        SemanticSurfaceId::Id(id) => {
-            semantic_surface_string = semantic_surface_id.to_string();
-        }
-        SemanticSurfaceId::Str(s) => {
-            semantic_surface_string = semantic_surface_id.to_string();
-        }
-        SemanticSurfaceId::None => {
-            semantic_surface_string = "No_semantic_surface_sting_found".to_string();
-        }
+// Metadata of a building or another top-level city object, written as <building_id>.json next
+// to the obj files
+#[derive(Debug, Serialize)]
+pub struct BuildingMetadata {
+    pub building_id: String,
+    pub class: String,
+    // Input file or archive member (e.g. tiles.zip/12/2200/1340.gml) the city object was read from
+    pub source: Option<String>,
+    // CRS the coordinates were reprojected into, e.g. EPSG:25832
+    pub crs: Option<String>,
+    // Level of detail that was converted, null if all of them were converted
+    pub lod: Option<u8>,
+    pub dx: f64,
+    pub dy: f64,
+    pub dz: f64,
+    // Row-major 4x4 matrix from the coordinates in `crs` onto the written coordinates, including
+    // the translation into the local CRS, the scale, the up axis and the offset
+    pub transform: [[f64; 4]; 4],
+    // gml:ids of the polygons that faced into their solid and were turned around
+    pub flipped_polygons: Vec<String>,
+    pub files: Vec<FileMetadata>,
+}
+
+// Number of polygons of one class that were written, with the triangles and vertices of their
+// triangulation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct GeometryCounts {
+    pub polygons: usize,
+    pub triangles: usize,
+    pub vertices: usize,
+}
+
+impl AddAssign for GeometryCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.polygons += other.polygons;
+        self.triangles += other.triangles;
+        self.vertices += other.vertices;
     }
-    let metadata = Metadata {
-        building_id: building_id.to_string(),
-        class_gml_id: gml_id.to_string(),
-        multi_surface_gml_id: stuff_gml_id.to_string(),
-        polygon_gml_id: semantic_surface_string,
-        thematic_role: thematic_role.to_string(),
-        dx: dx.to_string(),
-        dy: dy.to_string(),
-        dz: dz.to_string(),
-    };
+}
+
+// Entry of a building or another top-level city object in the manifest
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CityObjectManifest {
+    pub building_id: String,
+    pub class: String,
+    pub source: Option<String>,
+    pub crs: Option<String>,
+    pub lod: Option<u8>,
+    pub dx: f64,
+    pub dy: f64,
+    pub dz: f64,
+    pub transform: [[f64; 4]; 4],
+    pub flipped_polygons: Vec<String>,
+    // Written files, relative to the output directory of the run
+    pub files: Vec<String>,
+    // Counts per class, e.g. RoofSurface or BuildingInstallation
+    pub counts: BTreeMap<String, GeometryCounts>,
+    // Problems that did not prevent the conversion, e.g. polygons that could not be triangulated
+    pub warnings: Vec<String>,
+    // Files that could not be written
+    pub errors: Vec<String>,
+}
+
+// Entry of an input file or archive member in the manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct InputFileManifest {
+    pub path: String,
+    pub converted: bool,
+    pub error: Option<String>,
+}
+
+// Index of a whole run, written as manifest.json into the output directory
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub input_files: Vec<InputFileManifest>,
+    pub summary: ConversionSummary,
+    pub city_objects: Vec<CityObjectManifest>,
+}
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
 
-    let filename = format!("{}.json", metadata.polygon_gml_id);
+pub fn write_manifest(manifest: &Manifest, output_dir: &Path) -> Result<()> {
+    let file_path = output_dir.join(MANIFEST_FILE_NAME);
 
-    let file_path = Path::new(output_dir).join(filename);
+    let file = File::create(&file_path).map_err(write_error(&file_path))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), manifest)
+        .map_err(|e| write_error(&file_path)(e.into()))
+}
+
+// Appended to all file names if only a single level of detail is converted, e.g. "_lod2"
+pub fn lod_suffix(lod: Option<u8>) -> String {
+    lod.map(|lod| format!("_lod{}", lod)).unwrap_or_default()
+}
+
+pub fn write_json_metadata(building_metadata: &BuildingMetadata, output_dir: &Path) -> Result<()> {
+    let filename = format!(
+        "{}{}.json",
+        building_metadata.building_id,
+        lod_suffix(building_metadata.lod)
+    );
+
+    let file_path = output_dir.join(filename);
+
+    let file = File::create(&file_path).map_err(write_error(&file_path))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), building_metadata)
+        .map_err(|e| write_error(&file_path)(e.into()))
+}
+
+// Axis-aligned bounding box of a single building as read from an external file
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ImportedBoundingBox {
+    pub lower_corner: [f64; 3],
+    pub upper_corner: [f64; 3],
+}
+
+// Imported bounding boxes keyed by the gml:id of the building
+pub type ImportedBoundingBoxes = HashMap<String, ImportedBoundingBox>;
+
+// Reads the bounding boxes from either a JSON file of the form
+// { "<building_id>": { "lower_corner": [x, y, z], "upper_corner": [x, y, z] }, ... }
+// or a CSV file with the columns building_id,x_min,y_min,z_min,x_max,y_max,z_max
+pub fn import_bbox_from_file(path: &Path) -> Result<ImportedBoundingBoxes> {
+    read_bbox_file(path).map_err(|source| Error::Import {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn read_bbox_file(path: &Path) -> std::io::Result<ImportedBoundingBoxes> {
+    let content = std::fs::read_to_string(path)?;
+
+    let is_json = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    parse_bboxes(&content, is_json)
+}
+
+fn parse_bboxes(content: &str, is_json: bool) -> std::io::Result<ImportedBoundingBoxes> {
+    if is_json {
+        return serde_json::from_str(content).map_err(std::io::Error::from);
+    }
 
-    let file = match File::create(&file_path) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Failed to create JSON file: {}", e);
-            return;
+    let mut imported_bboxes = ImportedBoundingBoxes::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
-    };
 
-    let writer = BufWriter::new(file);
-    if let Err(e) = serde_json::to_writer_pretty(writer, &metadata) {
-        eprintln!("Failed to write JSON metadata: {}", e);
+        let fields: Vec<&str> = line.split([',', ';']).map(str::trim).collect();
+        let coordinates: std::result::Result<Vec<f64>, _> =
+            fields.iter().skip(1).map(|f| f.parse::<f64>()).collect();
+
+        match coordinates {
+            Ok(c) if fields.len() == 7 => {
+                imported_bboxes.insert(
+                    fields[0].to_string(),
+                    ImportedBoundingBox {
+                        lower_corner: [c[0], c[1], c[2]],
+                        upper_corner: [c[3], c[4], c[5]],
+                    },
+                );
+            }
+            // Tolerate a header line
+            Err(_) if line_number == 0 => continue,
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "invalid bounding box in line {}: expected building_id,x_min,y_min,z_min,x_max,y_max,z_max",
+                        line_number + 1
+                    ),
+                ));
+            }
+        }
     }
-}
 
-pub fn import_bbox_from_file() {
-    // todo: muss noch implementiert werden
+    Ok(imported_bboxes)
 }
 
+// Writes the triangles (and the bounding box, if present) into an obj file. Normals, if given,
+// belong to the vertex with the same index.
 pub fn write_obj_file(
     input_points: Vec<[f64; 3]>,
+    normals: Option<&[[f64; 3]]>,
     triangles: Vec<u32>,
-    building_id: &Id,
     semantic_surface_id: SemanticSurfaceId,
-    thematic_role: &str,
-    dx: f64,
-    dy: f64,
-    dz: f64,
-    bbox: &(Vec<[f64; 3]>, Vec<[u64; 3]>),
-    gml_id: &Id,
-    stuff_gml_id: &Id,
-) {
-    let args = Args::parse();
-    let building_id_string = building_id.to_string();
-    let mut semantic_surface_string: String = "default".to_string();
-
-    match semantic_surface_id {
-        SemanticSurfaceId::Id(id) => {
-            semantic_surface_string = semantic_surface_id.to_string();
-        }
-        SemanticSurfaceId::Str(s) => {
-            semantic_surface_string = semantic_surface_id.to_string();
-        }
-        SemanticSurfaceId::None => {
-            semantic_surface_string = "No_semantic_surface_sting_found".to_string();
-        }
+    bbox: &BoundingBoxGeometry,
+    material: Option<&str>,
+    output_dir: &Path,
+) -> Result<()> {
+    let semantic_surface_string = match semantic_surface_id {
+        SemanticSurfaceId::None => "No_semantic_surface_sting_found".to_string(),
+        _ => semantic_surface_id.to_string(),
+    };
+
+    if !triangles.len().is_multiple_of(3) {
+        return Err(Error::InvalidTriangles(semantic_surface_string));
     }
 
     let filename = format!("{}.obj", semantic_surface_string);
-    let file_path = Path::new(&args.output).join(filename);
-
-    let file = match File::create(&file_path) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("Failed to create file: {}", e);
-            return;
-        }
-    };
+    let file_path = output_dir.join(filename);
 
+    let file = File::create(&file_path).map_err(write_error(&file_path))?;
     let mut writer = BufWriter::new(file);
+    write_obj_content(
+        &mut writer,
+        &input_points,
+        normals,
+        &triangles,
+        bbox,
+        material,
+    )
+    .and_then(|_| writer.flush())
+    .map_err(write_error(&file_path))
+}
 
-    // Write main geometry vertices
-    for point in &input_points {
-        if let Err(e) = writeln!(writer, "v {} {} {}", point[0], point[1], point[2]) {
-            eprintln!("Failed to write vertex: {}", e);
-            return;
-        }
+fn write_obj_content(
+    writer: &mut impl Write,
+    input_points: &[[f64; 3]],
+    normals: Option<&[[f64; 3]]>,
+    triangles: &[u32],
+    bbox: &BoundingBoxGeometry,
+    material: Option<&str>,
+) -> std::io::Result<()> {
+    // Reference the shared material library
+    if material.is_some() {
+        writeln!(writer, "mtllib {}", MTL_FILE_NAME)?;
     }
 
-    let base_vertex_count = input_points.len();
+    // Write main geometry vertices
+    for point in input_points {
+        writeln!(writer, "v {} {} {}", point[0], point[1], point[2])?;
+    }
+    for normal in normals.unwrap_or_default() {
+        writeln!(writer, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+    }
 
     // Write triangle faces
-    if triangles.len() % 3 != 0 {
-        eprintln!("Triangle index list is not a multiple of 3.");
-        return;
+    if let Some(material) = material {
+        writeln!(writer, "usemtl {}", material)?;
     }
 
     for face in triangles.chunks(3) {
-        if let Err(e) = writeln!(writer, "f {} {} {}", face[0] + 1, face[1] + 1, face[2] + 1) {
-            eprintln!("Failed to write face: {}", e);
-            return;
+        match normals {
+            Some(_) => write_face(writer, face, face)?,
+            None => writeln!(writer, "f {} {} {}", face[0] + 1, face[1] + 1, face[2] + 1)?,
         }
     }
 
-    if args.add_bb {
-        // Write bounding box vertices
-        let (bbox_vertices, bbox_triangles) = bbox;
-        for point in bbox_vertices {
-            if let Err(e) = writeln!(writer, "v {} {} {}", point[0], point[1], point[2]) {
-                eprintln!("Failed to write bbox vertex: {}", e);
-                return;
-            }
+    write_bbox(writer, bbox, input_points.len(), material.is_some())
+}
+
+// Writes a face with a normal per vertex as f v//vn
+fn write_face(writer: &mut impl Write, face: &[u32], normals: &[u32]) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "f {}//{} {}//{} {}//{}",
+        face[0] + 1,
+        normals[0] + 1,
+        face[1] + 1,
+        normals[1] + 1,
+        face[2] + 1,
+        normals[2] + 1
+    )
+}
+
+// Appends the bounding box reflectors, if present, to an obj file that already holds
+// vertex_offset vertices
+fn write_bbox(
+    writer: &mut impl Write,
+    bbox: &BoundingBoxGeometry,
+    vertex_offset: usize,
+    with_material: bool,
+) -> std::io::Result<()> {
+    let (bbox_vertices, bbox_triangles) = bbox;
+    if bbox_vertices.is_empty() {
+        return Ok(());
+    }
+
+    // Write bounding box vertices
+    for point in bbox_vertices {
+        writeln!(writer, "v {} {} {}", point[0], point[1], point[2])?;
+    }
+
+    if with_material {
+        writeln!(writer, "usemtl {}", BOUNDING_BOX_MATERIAL)?;
+    }
+
+    // Write bounding box triangles
+    for face in bbox_triangles {
+        writeln!(
+            writer,
+            "f {} {} {}",
+            vertex_offset + face[0] as usize + 1,
+            vertex_offset + face[1] as usize + 1,
+            vertex_offset + face[2] as usize + 1,
+        )?;
+    }
+
+    Ok(())
+}
+
+// Triangles of one semantic surface that are written as an object of a grouped obj file
+#[derive(Debug)]
+pub struct ObjGroup<'a> {
+    pub object_name: &'a str,
+    pub group_name: &'a str,
+    pub vertices: &'a [[f64; 3]],
+    // Normals of the vertices with the same index
+    pub normals: Option<&'a [[f64; 3]]>,
+    pub triangles: &'a [u32],
+}
+
+// Writes several groups into one obj file. Identical vertices and normals are only written once
+// and shared between all objects of the file.
+pub fn write_grouped_obj_file(
+    groups: &[ObjGroup],
+    file_stem: &str,
+    bbox: &BoundingBoxGeometry,
+    palette: Option<&MaterialPalette>,
+    output_dir: &Path,
+) -> Result<()> {
+    let file_path = output_dir.join(format!("{}.obj", file_stem));
+
+    // Deduplicate the vertices over all groups
+    let mut shared_vertices: Vec<[f64; 3]> = Vec::new();
+    let mut vertex_lookup: HashMap<[u64; 3], u32> = HashMap::new();
+    let mut group_faces: Vec<Vec<u32>> = Vec::with_capacity(groups.len());
+    let mut shared_normals: Vec<[f64; 3]> = Vec::new();
+    let mut normal_lookup: HashMap<[u64; 3], u32> = HashMap::new();
+    let mut group_normals: Vec<Vec<u32>> = Vec::with_capacity(groups.len());
+    for group in groups {
+        if !group.triangles.len().is_multiple_of(3) {
+            return Err(Error::InvalidTriangles(group.object_name.to_string()));
         }
 
-        // Write bounding box edges
-        let bbox_vertex_offset = base_vertex_count;
-        // Write bounding box triangles
-        for face in bbox_triangles {
-            if let Err(e) = writeln!(
+        let local_to_shared: Vec<u32> = group
+            .vertices
+            .iter()
+            .map(|v| {
+                let key = [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()];
+                *vertex_lookup.entry(key).or_insert_with(|| {
+                    shared_vertices.push(*v);
+                    (shared_vertices.len() - 1) as u32
+                })
+            })
+            .collect();
+
+        group_faces.push(
+            group
+                .triangles
+                .iter()
+                .map(|idx| local_to_shared[*idx as usize])
+                .collect(),
+        );
+
+        if let Some(normals) = group.normals {
+            let local_to_shared: Vec<u32> = normals
+                .iter()
+                .map(|n| {
+                    let key = [n[0].to_bits(), n[1].to_bits(), n[2].to_bits()];
+                    *normal_lookup.entry(key).or_insert_with(|| {
+                        shared_normals.push(*n);
+                        (shared_normals.len() - 1) as u32
+                    })
+                })
+                .collect();
+            group_normals.push(
+                group
+                    .triangles
+                    .iter()
+                    .map(|idx| local_to_shared[*idx as usize])
+                    .collect(),
+            );
+        } else {
+            group_normals.push(Vec::new());
+        }
+    }
+
+    let file = File::create(&file_path).map_err(write_error(&file_path))?;
+    let mut writer = BufWriter::new(file);
+    write_grouped_obj_content(
+        &mut writer,
+        groups,
+        &shared_vertices,
+        &group_faces,
+        &shared_normals,
+        &group_normals,
+        bbox,
+        palette,
+    )
+    .and_then(|_| writer.flush())
+    .map_err(write_error(&file_path))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_grouped_obj_content(
+    writer: &mut impl Write,
+    groups: &[ObjGroup],
+    shared_vertices: &[[f64; 3]],
+    group_faces: &[Vec<u32>],
+    shared_normals: &[[f64; 3]],
+    group_normals: &[Vec<u32>],
+    bbox: &BoundingBoxGeometry,
+    palette: Option<&MaterialPalette>,
+) -> std::io::Result<()> {
+    if palette.is_some() {
+        writeln!(writer, "mtllib {}", MTL_FILE_NAME)?;
+    }
+
+    for point in shared_vertices {
+        writeln!(writer, "v {} {} {}", point[0], point[1], point[2])?;
+    }
+    for normal in shared_normals {
+        writeln!(writer, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+    }
+
+    for ((group, faces), normals) in groups.iter().zip(group_faces).zip(group_normals) {
+        writeln!(writer, "o {}", group.object_name)?;
+        writeln!(writer, "g {}", group.group_name)?;
+        if let Some(palette) = palette {
+            writeln!(
                 writer,
-                "f {} {} {}",
-                bbox_vertex_offset + face[0] as usize + 1,
-                bbox_vertex_offset + face[1] as usize + 1,
-                bbox_vertex_offset + face[2] as usize + 1,
-            ) {
-                eprintln!("Failed to write bbox triangle: {}", e);
-                return;
+                "usemtl {}",
+                material_name(palette, group.group_name)
+            )?;
+        }
+        if normals.is_empty() {
+            for face in faces.chunks(3) {
+                writeln!(writer, "f {} {} {}", face[0] + 1, face[1] + 1, face[2] + 1)?;
             }
+        } else {
+            for (face, face_normals) in faces.chunks(3).zip(normals.chunks(3)) {
+                write_face(writer, face, face_normals)?;
+            }
+        }
+    }
+
+    if !bbox.0.is_empty() {
+        writeln!(writer, "o bounding_box")?;
+        write_bbox(writer, bbox, shared_vertices.len(), palette.is_some())?;
+    }
+
+    writer.flush()
+}
+
+// Name of the material library that is shared by all obj files of an output directory
+pub const MTL_FILE_NAME: &str = "citygml2obj.mtl";
+
+// Material used for the bounding box reflectors
+pub const BOUNDING_BOX_MATERIAL: &str = "BoundingBox";
+
+// Diffuse colours (RGB in the range 0..1) keyed by the class name of the semantic surface
+pub type MaterialPalette = HashMap<String, [f64; 3]>;
+
+// Material of surfaces without a class and of classes the palette has no colour for
+pub const UNKNOWN_MATERIAL: &str = "UnknownSurface";
+
+// Colours used for all classes that are not overridden by a custom palette. Every class the
+// converter writes has a colour of its own; see material_name for the others.
+pub fn default_material_palette() -> MaterialPalette {
+    [
+        // Boundary surfaces of buildings, bridges and tunnels
+        ("RoofSurface", [0.8, 0.1, 0.1]),
+        ("WallSurface", [0.95, 0.95, 0.95]),
+        ("GroundSurface", [0.35, 0.35, 0.35]),
+        ("OuterCeilingSurface", [0.85, 0.6, 0.2]),
+        ("OuterFloorSurface", [0.6, 0.5, 0.4]),
+        ("ClosureSurface", [0.7, 0.9, 0.7]),
+        ("InteriorWallSurface", [0.9, 0.88, 0.8]),
+        ("CeilingSurface", [0.92, 0.92, 0.85]),
+        ("FloorSurface", [0.65, 0.55, 0.45]),
+        ("GenericThematicSurface", [0.6, 0.6, 0.6]),
+        // Openings
+        ("WindowSurface", [0.4, 0.7, 0.95]),
+        ("Window", [0.4, 0.7, 0.95]),
+        ("DoorSurface", [0.55, 0.35, 0.15]),
+        ("Door", [0.55, 0.35, 0.15]),
+        // Buildings
+        ("Building", [0.8, 0.8, 0.8]),
+        ("BuildingPart", [0.75, 0.75, 0.75]),
+        ("BuildingInstallation", [0.6, 0.3, 0.7]),
+        ("BuildingConstructiveElement", [0.9, 0.5, 0.1]),
+        ("BuildingFurniture", [0.7, 0.55, 0.4]),
+        ("BuildingRoom", [0.85, 0.85, 0.75]),
+        ("BuildingUnit", [0.8, 0.8, 0.7]),
+        ("Story", [0.8, 0.75, 0.7]),
+        // Bridges
+        ("Bridge", [0.65, 0.6, 0.55]),
+        ("BridgePart", [0.6, 0.55, 0.5]),
+        ("BridgeInstallation", [0.55, 0.35, 0.6]),
+        ("BridgeConstructiveElement", [0.8, 0.45, 0.15]),
+        ("BridgeFurniture", [0.65, 0.5, 0.35]),
+        ("BridgeRoom", [0.75, 0.7, 0.65]),
+        // Tunnels
+        ("Tunnel", [0.45, 0.4, 0.35]),
+        ("TunnelPart", [0.4, 0.35, 0.3]),
+        ("TunnelInstallation", [0.45, 0.25, 0.5]),
+        ("TunnelConstructiveElement", [0.7, 0.4, 0.1]),
+        ("TunnelFurniture", [0.55, 0.45, 0.3]),
+        ("HollowSpace", [0.5, 0.45, 0.4]),
+        // Transportation
+        ("Road", [0.3, 0.3, 0.3]),
+        ("Railway", [0.4, 0.3, 0.25]),
+        ("Track", [0.5, 0.4, 0.3]),
+        ("Square", [0.55, 0.55, 0.5]),
+        ("TransportationComplex", [0.35, 0.35, 0.35]),
+        ("Section", [0.3, 0.3, 0.32]),
+        ("Intersection", [0.32, 0.3, 0.3]),
+        ("TrafficSpace", [0.3, 0.3, 0.35]),
+        ("AuxiliaryTrafficSpace", [0.5, 0.5, 0.45]),
+        ("ClearanceSpace", [0.7, 0.7, 0.75]),
+        ("TrafficArea", [0.25, 0.25, 0.25]),
+        ("AuxiliaryTrafficArea", [0.45, 0.45, 0.45]),
+        ("Marking", [1.0, 1.0, 1.0]),
+        ("Hole", [0.15, 0.15, 0.15]),
+        ("HoleSurface", [0.15, 0.15, 0.15]),
+        // Water
+        ("WaterBody", [0.15, 0.35, 0.75]),
+        ("Waterway", [0.2, 0.4, 0.8]),
+        ("WaterSurface", [0.1, 0.3, 0.8]),
+        ("WaterGroundSurface", [0.35, 0.3, 0.2]),
+        ("WaterClosureSurface", [0.5, 0.7, 0.9]),
+        // Vegetation, city furniture and generic city objects
+        ("SolitaryVegetationObject", [0.1, 0.6, 0.1]),
+        ("PlantCover", [0.2, 0.7, 0.2]),
+        ("CityFurniture", [0.5, 0.5, 0.2]),
+        ("OtherConstruction", [0.6, 0.6, 0.55]),
+        ("GenericCityObject", [0.5, 0.5, 0.55]),
+        ("GenericOccupiedSpace", [0.5, 0.5, 0.55]),
+        ("GenericUnoccupiedSpace", [0.55, 0.55, 0.6]),
+        ("GenericLogicalSpace", [0.6, 0.6, 0.65]),
+        ("CityObjectGroup", [0.5, 0.5, 0.5]),
+        (UNKNOWN_MATERIAL, [0.5, 0.5, 0.5]),
+        (BOUNDING_BOX_MATERIAL, [1.0, 0.85, 0.0]),
+    ]
+    .into_iter()
+    .map(|(class, colour)| (class.to_string(), colour))
+    .collect()
+}
+
+// The material of a class: the class itself if the palette has a colour for it, otherwise the
+// one of unknown surfaces, so every usemtl refers to a material of the library
+pub fn material_name<'a>(palette: &MaterialPalette, class: &'a str) -> &'a str {
+    if palette.contains_key(class) {
+        class
+    } else {
+        UNKNOWN_MATERIAL
+    }
+}
+
+// Reads a custom palette from a JSON file of the form { "RoofSurface": [1.0, 0.0, 0.0], ... }
+// and merges it into the default palette
+pub fn import_palette_from_file(path: &Path) -> Result<MaterialPalette> {
+    std::fs::read_to_string(path)
+        .and_then(|content| parse_palette(&content))
+        .map_err(|source| Error::Import {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+// All colours of a palette share one scale: 0..255 if any component is greater than 1, otherwise
+// 0..1. Deciding per colour would read e.g. [1, 1, 1] next to [200, 0, 0] as white.
+fn parse_palette(content: &str) -> std::io::Result<MaterialPalette> {
+    let custom_palette: MaterialPalette =
+        serde_json::from_str(content).map_err(std::io::Error::from)?;
+
+    if let Some(class) = custom_palette
+        .iter()
+        .find(|(_, colour)| colour.iter().any(|c| !(0.0..=255.0).contains(c)))
+        .map(|(class, _)| class)
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the colour of {} is not in the range 0..1 or 0..255", class),
+        ));
+    }
+    let is_8_bit = custom_palette.values().flatten().any(|c| *c > 1.0);
+
+    let mut palette = default_material_palette();
+    for (class, colour) in custom_palette {
+        let colour = if is_8_bit {
+            colour.map(|c| c / 255.0)
+        } else {
+            colour
+        };
+        palette.insert(class, colour);
+    }
+
+    Ok(palette)
+}
+
+// Writes the material library with one material per class into the output directory
+pub fn write_mtl_file(palette: &MaterialPalette, output_dir: &Path) -> Result<()> {
+    let file_path = output_dir.join(MTL_FILE_NAME);
+    write_mtl_content(palette, &file_path).map_err(write_error(&file_path))
+}
+
+fn write_mtl_content(palette: &MaterialPalette, file_path: &Path) -> std::io::Result<()> {
+    // The materials the writers fall back to are always part of the library
+    let mut palette = palette.clone();
+    for class in [UNKNOWN_MATERIAL, BOUNDING_BOX_MATERIAL] {
+        palette
+            .entry(class.to_string())
+            .or_insert_with(|| default_material_palette()[class]);
+    }
+    let mut classes: Vec<&String> = palette.keys().collect();
+    classes.sort();
+
+    let mut writer = BufWriter::new(File::create(file_path)?);
+    for class in classes {
+        let [r, g, b] = palette[class];
+        writeln!(writer, "newmtl {}", class)?;
+        writeln!(writer, "Ka 0 0 0")?;
+        writeln!(writer, "Kd {} {} {}", r, g, b)?;
+        writeln!(writer, "d 1")?;
+        writeln!(writer, "illum 1")?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_bounding_boxes_are_read_after_a_header() {
+        let content = "building_id,x_min,y_min,z_min,x_max,y_max,z_max\n\
+                       # comment\n\
+                       \n\
+                       B1, 1, 2, 3, 4, 5, 6\n\
+                       B2;-1;-2;-3;0.5;0.5;0.5\n";
+
+        let bboxes = parse_bboxes(content, false).unwrap();
+
+        assert_eq!(bboxes.len(), 2);
+        assert_eq!(bboxes["B1"].lower_corner, [1.0, 2.0, 3.0]);
+        assert_eq!(bboxes["B1"].upper_corner, [4.0, 5.0, 6.0]);
+        assert_eq!(bboxes["B2"].lower_corner, [-1.0, -2.0, -3.0]);
+        assert_eq!(bboxes["B2"].upper_corner, [0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn csv_bounding_boxes_with_wrong_columns_are_rejected() {
+        for content in [
+            "B1,1,2,3,4,5\n",
+            "B1,1,2,3,4,5,6,7\n",
+            "B1,1,2,3,4,5,6\nB2,1,2,3,4,5\n",
+            // Only the first line may be a header
+            "B1,1,2,3,4,5,6\nbuilding_id,x_min,y_min,z_min,x_max,y_max,z_max\n",
+        ] {
+            let error = parse_bboxes(content, false).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", content);
         }
     }
 
-    if args.add_json {
-        write_json_metadata(
-            building_id,
-            semantic_surface_id,
-            thematic_role,
-            &args.output,
-            dx,
-            dy,
-            dz,
-            gml_id,
-            stuff_gml_id,
+    #[test]
+    fn json_bounding_boxes_are_read_by_building_id() {
+        let content = r#"{
+            "B1": { "lower_corner": [1, 2, 3], "upper_corner": [4, 5, 6] }
+        }"#;
+
+        let bboxes = parse_bboxes(content, true).unwrap();
+
+        assert_eq!(bboxes["B1"].lower_corner, [1.0, 2.0, 3.0]);
+        assert_eq!(bboxes["B1"].upper_corner, [4.0, 5.0, 6.0]);
+        assert!(parse_bboxes(r#"{ "B1": { "lower_corner": [1, 2, 3] } }"#, true).is_err());
+    }
+
+    #[test]
+    fn palette_colours_share_one_scale() {
+        let palette =
+            parse_palette(r#"{"RoofSurface": [255, 0, 0], "WallSurface": [1, 1, 1]}"#).unwrap();
+        assert_eq!(palette["RoofSurface"], [1.0, 0.0, 0.0]);
+        assert_eq!(palette["WallSurface"], [1.0 / 255.0; 3]);
+
+        let palette = parse_palette(r#"{"RoofSurface": [1, 0.5, 0]}"#).unwrap();
+        assert_eq!(palette["RoofSurface"], [1.0, 0.5, 0.0]);
+        assert_eq!(
+            palette["WallSurface"],
+            default_material_palette()["WallSurface"]
         );
+
+        assert!(parse_palette(r#"{"RoofSurface": [256, 0, 0]}"#).is_err());
+        assert!(parse_palette(r#"{"RoofSurface": [-1, 0, 0]}"#).is_err());
     }
 }
@@ -0,0 +1,243 @@
+use rust_citygml2obj::cityjson_functions::CityJsonGeometry;
+use rust_citygml2obj::write_functions::{MTL_FILE_NAME, MaterialPalette, default_material_palette};
+use rust_citygml2obj::{ConversionOptions, convert_file};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+        .join(name)
+}
+
+// An empty output directory of its own for every test, as the tests run in parallel
+fn output_dir(test_name: &str) -> PathBuf {
+    let output = std::env::temp_dir()
+        .join("rust_citygml2obj_tests")
+        .join(test_name);
+    let _ = fs::remove_dir_all(&output);
+    fs::create_dir_all(&output).unwrap();
+    output
+}
+
+fn face_count(obj_file: &Path) -> usize {
+    fs::read_to_string(obj_file)
+        .unwrap()
+        .lines()
+        .filter(|line| line.starts_with("f "))
+        .count()
+}
+
+#[test]
+fn citygml2_water_boundary_surfaces_are_converted() {
+    let output = output_dir("citygml2_water_boundary_surfaces_are_converted");
+    let options = ConversionOptions::new(&output);
+
+    let summary = convert_file(&fixture("water_citygml2.gml"), &options).unwrap();
+
+    assert_eq!(summary.city_objects_converted, 1);
+    assert_eq!(summary.city_objects_failed, 0);
+    for polygon_id in ["WS1_P1", "WG1_P1", "WC1_P1"] {
+        let obj_file = output.join(format!("{}.obj", polygon_id));
+        assert_eq!(face_count(&obj_file), 2, "{}", obj_file.display());
+    }
+}
+
+// Names of the materials used by all obj files and defined by the material library
+fn materials(output: &Path) -> (HashSet<String>, HashSet<String>) {
+    let mut used = HashSet::new();
+    for entry in fs::read_dir(output).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "obj") {
+            used.extend(
+                fs::read_to_string(&path)
+                    .unwrap()
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("usemtl "))
+                    .map(str::to_string),
+            );
+        }
+    }
+    let defined = fs::read_to_string(output.join(MTL_FILE_NAME))
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix("newmtl "))
+        .map(str::to_string)
+        .collect();
+    (used, defined)
+}
+
+#[test]
+fn every_used_material_is_defined_in_the_material_library() {
+    let custom_palette: MaterialPalette = [("RoofSurface".to_string(), [1.0, 0.0, 0.0])].into();
+    for (name, palette) in [
+        ("default", default_material_palette()),
+        ("custom", custom_palette),
+    ] {
+        for (mode, group_sc, group_scomp, group_building) in [
+            ("polygons", false, false, false),
+            ("sc", true, false, false),
+            ("scomp", false, true, false),
+            ("building", false, false, true),
+        ] {
+            let test_name = format!("every_used_material_is_defined_{}_{}", name, mode);
+            let output = output_dir(&test_name);
+            let options = ConversionOptions {
+                material_palette: Some(palette.clone()),
+                add_bb: true,
+                group_sc,
+                group_scomp,
+                group_building,
+                ..ConversionOptions::new(&output)
+            };
+
+            for input in ["water_citygml2.gml", "bridge_citygml2.gml"] {
+                convert_file(&fixture(input), &options).unwrap();
+            }
+
+            let (used, defined) = materials(&output);
+            assert!(!used.is_empty(), "{}", test_name);
+            let undefined: Vec<_> = used.difference(&defined).collect();
+            assert!(undefined.is_empty(), "{}: {:?}", test_name, undefined);
+        }
+    }
+}
+
+#[test]
+fn city_objects_without_geometry_fail_in_every_whole_city_object_output() {
+    for (mode, group_building, write_glb, cityjson) in [
+        ("building", true, false, None),
+        ("glb", false, true, None),
+        ("cityjson", false, false, Some(CityJsonGeometry::Triangles)),
+    ] {
+        let output = output_dir(&format!("city_objects_without_geometry_{}", mode));
+        let options = ConversionOptions {
+            group_building,
+            write_glb,
+            cityjson,
+            ..ConversionOptions::new(&output)
+        };
+
+        let summary =
+            convert_file(&fixture("building_without_geometry_citygml2.gml"), &options).unwrap();
+
+        assert_eq!(summary.city_objects_converted, 0, "{}", mode);
+        assert_eq!(summary.city_objects_failed, 1, "{}", mode);
+        assert!(!output.join("E1.obj").exists(), "{}", mode);
+        assert!(
+            !output
+                .join("building_without_geometry_citygml2.glb")
+                .exists(),
+            "{}",
+            mode
+        );
+    }
+}
+
+#[test]
+fn geographic_gml_coordinates_are_read_latitude_first() {
+    let output = output_dir("geographic_gml_coordinates_are_read_latitude_first");
+    let options = ConversionOptions {
+        target_crs: Some(25832),
+        ..ConversionOptions::new(&output)
+    };
+
+    convert_file(&fixture("geographic_citygml2.gml"), &options).unwrap();
+
+    // 48.137° N, 11.575° E lies in Munich, in UTM zone 32
+    let obj = fs::read_to_string(output.join("G1_GS_P1.obj")).unwrap();
+    for line in obj.lines().filter(|line| line.starts_with("v ")) {
+        let coordinates: Vec<f64> = line[2..]
+            .split_whitespace()
+            .map(|c| c.parse().unwrap())
+            .collect();
+        assert!((691_000.0..692_000.0).contains(&coordinates[0]), "{}", line);
+        assert!(
+            (5_334_000.0..5_335_000.0).contains(&coordinates[1]),
+            "{}",
+            line
+        );
+    }
+}
+
+// The JSON chunk of a binary glTF file, which directly follows the 12 byte header
+fn glb_json(glb_file: &Path) -> serde_json::Value {
+    let glb = fs::read(glb_file).unwrap();
+    let length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+    serde_json::from_slice(&glb[20..20 + length]).unwrap()
+}
+
+#[test]
+fn glb_output_has_one_named_node_per_building() {
+    let output = output_dir("glb_output_has_one_named_node_per_building");
+    let options = ConversionOptions {
+        write_glb: true,
+        ..ConversionOptions::new(&output)
+    };
+
+    let summary = convert_file(&fixture("two_buildings_citygml2.gml"), &options).unwrap();
+
+    assert_eq!(summary.city_objects_converted, 2);
+    let glb_files: Vec<_> = fs::read_dir(&output)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().ends_with(".glb"))
+        .collect();
+    assert_eq!(glb_files, ["two_buildings_citygml2.glb"]);
+
+    let gltf = glb_json(&output.join("two_buildings_citygml2.glb"));
+    let names: Vec<&str> = gltf["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|node| node["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["T1", "T2"]);
+    assert_eq!(gltf["scenes"][0]["nodes"], serde_json::json!([0, 1]));
+    assert_eq!(gltf["meshes"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn cityjson_output_is_one_dataset_with_a_reference_system() {
+    let output = output_dir("cityjson_output_is_one_dataset_with_a_reference_system");
+    let options = ConversionOptions {
+        cityjson: Some(CityJsonGeometry::Polygons),
+        ..ConversionOptions::new(&output)
+    };
+
+    convert_file(&fixture("two_buildings_citygml2.gml"), &options).unwrap();
+
+    let cityjson: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(output.join("two_buildings_citygml2.city.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        cityjson["metadata"]["referenceSystem"],
+        "https://www.opengis.net/def/crs/EPSG/0/25832"
+    );
+    let mut ids: Vec<&String> = cityjson["CityObjects"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect();
+    ids.sort();
+    assert_eq!(ids, ["T1", "T2"]);
+    // Both buildings refer to their own vertices at their real-world location
+    let translate = &cityjson["transform"]["translate"];
+    let vertices = cityjson["vertices"].as_array().unwrap();
+    let x = |index: &serde_json::Value| {
+        let vertex = &vertices[index.as_u64().unwrap() as usize];
+        vertex[0].as_f64().unwrap() * 0.001 + translate[0].as_f64().unwrap()
+    };
+    for (id, (lower, upper)) in [
+        ("T1", (690_000.0, 690_010.0)),
+        ("T2", (690_020.0, 690_030.0)),
+    ] {
+        let ring = &cityjson["CityObjects"][id]["geometry"][0]["boundaries"][0][0];
+        for index in ring.as_array().unwrap() {
+            assert!((lower - 1e-6..=upper + 1e-6).contains(&x(index)), "{}", id);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<core:CityModel xmlns:core="http://www.opengis.net/citygml/2.0" xmlns:brid="http://www.opengis.net/citygml/bridge/2.0" xmlns:gml="http://www.opengis.net/gml">
+  <core:cityObjectMember>
+    <brid:Bridge gml:id="BR1">
+      <brid:consistsOfBridgePart>
+        <brid:BridgePart gml:id="BP1">
+          <brid:boundedBy>
+            <brid:WallSurface gml:id="BP1_W1">
+              <brid:lod3MultiSurface>
+                <gml:MultiSurface>
+                  <gml:surfaceMember>
+                    <gml:Polygon gml:id="BP1_W1_P1">
+                      <gml:exterior>
+                        <gml:LinearRing>
+                          <gml:posList srsDimension="3">0 0 0 10 0 0 10 0 3 0 0 3 0 0 0</gml:posList>
+                        </gml:LinearRing>
+                      </gml:exterior>
+                    </gml:Polygon>
+                  </gml:surfaceMember>
+                </gml:MultiSurface>
+              </brid:lod3MultiSurface>
+              <brid:opening>
+                <brid:Window gml:id="BP1_W1_WIN1">
+                  <brid:lod3MultiSurface>
+                    <gml:MultiSurface>
+                      <gml:surfaceMember>
+                        <gml:Polygon gml:id="BP1_W1_WIN1_P1">
+                          <gml:exterior>
+                            <gml:LinearRing>
+                              <gml:posList srsDimension="3">4 0 1 6 0 1 6 0 2 4 0 2 4 0 1</gml:posList>
+                            </gml:LinearRing>
+                          </gml:exterior>
+                        </gml:Polygon>
+                      </gml:surfaceMember>
+                    </gml:MultiSurface>
+                  </brid:lod3MultiSurface>
+                </brid:Window>
+              </brid:opening>
+            </brid:WallSurface>
+          </brid:boundedBy>
+          <brid:boundedBy>
+            <brid:RoofSurface gml:id="BP1_R1">
+              <brid:lod3MultiSurface>
+                <gml:MultiSurface>
+                  <gml:surfaceMember>
+                    <gml:Polygon gml:id="BP1_R1_P1">
+                      <gml:exterior>
+                        <gml:LinearRing>
+                          <gml:posList srsDimension="3">0 0 3 10 0 3 10 5 3 0 5 3 0 0 3</gml:posList>
+                        </gml:LinearRing>
+                      </gml:exterior>
+                    </gml:Polygon>
+                  </gml:surfaceMember>
+                </gml:MultiSurface>
+              </brid:lod3MultiSurface>
+            </brid:RoofSurface>
+          </brid:boundedBy>
+        </brid:BridgePart>
+      </brid:consistsOfBridgePart>
+      <brid:outerBridgeInstallation>
+        <brid:BridgeInstallation gml:id="BI1">
+          <brid:lod3Geometry>
+            <gml:MultiSurface>
+              <gml:surfaceMember>
+                <gml:Polygon gml:id="BI1_P1">
+                  <gml:exterior>
+                    <gml:LinearRing>
+                      <gml:posList srsDimension="3">2 5 3 3 5 3 3 5 4 2 5 4 2 5 3</gml:posList>
+                    </gml:LinearRing>
+                  </gml:exterior>
+                </gml:Polygon>
+              </gml:surfaceMember>
+            </gml:MultiSurface>
+          </brid:lod3Geometry>
+        </brid:BridgeInstallation>
+      </brid:outerBridgeInstallation>
+    </brid:Bridge>
+  </core:cityObjectMember>
+</core:CityModel>
@@ -0,0 +1,50 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<core:CityModel xmlns:core="http://www.opengis.net/citygml/2.0" xmlns:wtr="http://www.opengis.net/citygml/waterbody/2.0" xmlns:gml="http://www.opengis.net/gml">
+  <core:cityObjectMember>
+    <wtr:WaterBody gml:id="WB1">
+      <wtr:boundedBy>
+        <wtr:WaterSurface gml:id="WS1">
+          <wtr:lod2Surface>
+            <gml:CompositeSurface>
+              <gml:surfaceMember>
+                <gml:Polygon gml:id="WS1_P1">
+                  <gml:exterior>
+                    <gml:LinearRing>
+                      <gml:posList srsDimension="3">0 0 0 20 0 0 20 10 0 0 10 0 0 0 0</gml:posList>
+                    </gml:LinearRing>
+                  </gml:exterior>
+                </gml:Polygon>
+              </gml:surfaceMember>
+            </gml:CompositeSurface>
+          </wtr:lod2Surface>
+        </wtr:WaterSurface>
+      </wtr:boundedBy>
+      <wtr:boundedBy>
+        <wtr:WaterGroundSurface gml:id="WG1">
+          <wtr:lod2Surface>
+            <gml:Polygon gml:id="WG1_P1">
+              <gml:exterior>
+                <gml:LinearRing>
+                  <gml:posList srsDimension="3">0 0 -2 0 10 -2 20 10 -2 20 0 -2 0 0 -2</gml:posList>
+                </gml:LinearRing>
+              </gml:exterior>
+            </gml:Polygon>
+          </wtr:lod2Surface>
+        </wtr:WaterGroundSurface>
+      </wtr:boundedBy>
+      <wtr:boundedBy>
+        <wtr:WaterClosureSurface gml:id="WC1">
+          <wtr:lod2Surface>
+            <gml:Polygon gml:id="WC1_P1">
+              <gml:exterior>
+                <gml:LinearRing>
+                  <gml:posList srsDimension="3">0 0 -2 20 0 -2 20 0 0 0 0 0 0 0 -2</gml:posList>
+                </gml:LinearRing>
+              </gml:exterior>
+            </gml:Polygon>
+          </wtr:lod2Surface>
+        </wtr:WaterClosureSurface>
+      </wtr:boundedBy>
+    </wtr:WaterBody>
+  </core:cityObjectMember>
+</core:CityModel>